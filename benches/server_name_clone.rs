@@ -0,0 +1,55 @@
+//! Compares cloning the connector's `ServerName` per connection with the cost of the
+//! connection itself. Run with `cargo bench --bench server_name_clone`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rustls::pki_types::ServerName;
+use tls_friend::client_connector::{ClientAcceptor, ClientConnector};
+use tls_friend::tls_setup::TlsSetup;
+use tokio::io::duplex;
+
+const ROUNDS: u32 = 1000;
+
+fn server_name_clone() -> Duration {
+    let name = ServerName::try_from("s1.testing-server.playit.cloud").unwrap().to_owned();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(black_box(&name).clone());
+    }
+    start.elapsed() / ROUNDS
+}
+
+async fn connect() -> Duration {
+    let acceptor = ClientAcceptor::from_server_tls(
+        TlsSetup::build_server(
+            include_bytes!("../src/res/server.key"),
+            include_bytes!("../src/res/server.crt"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let connector = TlsSetup::build_client(include_bytes!("../src/res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+    let connector = ClientConnector::tls("s1.testing-server.playit.cloud", connector).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let (server_io, client_io) = duplex(16 * 1024);
+        let (server_io, client_io) =
+            tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+        server_io.unwrap();
+        client_io.unwrap();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    tls_friend::install_crypto().unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+    println!("server name clone: {:?}", server_name_clone());
+    println!("connect:           {:?}", runtime.block_on(connect()));
+}
@@ -1,5 +1,20 @@
+use std::net::SocketAddr;
+
 use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+
+use crate::buf_stream::BufTlsStream;
+use crate::counting_stream::CountingStream;
+use crate::peek_io::PeekedIO;
+use crate::timeout_stream::TimeoutStream;
+use crate::tls_streams::{ClientStream, MaybeTlsStream, ServerStream};
 
+/// IO the stream types can wrap. The blanket impl covers every `AsyncRead + AsyncWrite`
+/// type and splits with [`tokio::io::split`], whose halves share a lock.
+///
+/// [`TcpStream`] and `UnixStream` split natively into owned halves without a lock: where
+/// the halves are used directly rather than through a stream type, call their own
+/// `into_split` and rejoin them with `reunite` instead.
 pub trait AsyncIO: AsyncRead + AsyncWrite + Sized + Send + Sync + Unpin + 'static {
     type ReadSide: AsyncRead + Sized + Send + Sync + Unpin;
     type WriteSide: AsyncWrite + Sized + Send + Sync + Unpin;
@@ -31,6 +46,60 @@ impl<T: AsyncRead + AsyncWrite + Sized + Send + Sync + Unpin + 'static> AsyncIO
     }
 }
 
+/// IO that knows its peer's address. The stream types get a `peer_addr` method whenever
+/// the IO they wrap implements this.
+pub trait HasPeerAddr {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr>;
+}
+
+impl HasPeerAddr for TcpStream {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+}
+
+impl<IO: AsyncIO + HasPeerAddr> HasPeerAddr for ClientStream<IO> {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl<IO: AsyncIO + HasPeerAddr> HasPeerAddr for ServerStream<IO> {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl<IO: AsyncIO + HasPeerAddr> HasPeerAddr for MaybeTlsStream<IO> {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl<IO: AsyncIO + HasPeerAddr> HasPeerAddr for PeekedIO<IO> {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl<IO: HasPeerAddr> HasPeerAddr for TimeoutStream<IO> {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl<IO: HasPeerAddr> HasPeerAddr for CountingStream<IO> {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl<IO: AsyncRead + HasPeerAddr> HasPeerAddr for BufTlsStream<IO> {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use tokio::net::{TcpListener, TcpStream};
@@ -47,4 +116,12 @@ mod test {
         let (read, write) = AsyncIO::into_split(tcp);
         let _v: TcpStream = AsyncIO::try_join(read, write).ok().unwrap();
     }
+
+    #[test]
+    fn foreign_io_test() {
+        fn assert_async_io<T: AsyncIO>() {}
+
+        assert_async_io::<tokio::io::BufStream<tokio::io::DuplexStream>>();
+        assert_async_io::<tokio::io::SimplexStream>();
+    }
 }
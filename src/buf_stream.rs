@@ -0,0 +1,95 @@
+use std::io::{Error, IoSlice};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, BufReader, ReadBuf};
+
+/// Buffers reads from a stream so line-oriented protocols can use
+/// [`AsyncBufReadExt`](tokio::io::AsyncBufReadExt) (`read_line`, `lines`, ...). Writes pass
+/// straight through to the inner stream.
+pub struct BufTlsStream<IO> {
+    inner: BufReader<IO>,
+}
+
+impl<IO: AsyncRead> BufTlsStream<IO> {
+    pub fn new(io: IO) -> Self {
+        BufTlsStream {
+            inner: BufReader::new(io),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize, io: IO) -> Self {
+        BufTlsStream {
+            inner: BufReader::with_capacity(capacity, io),
+        }
+    }
+
+    pub fn get_ref(&self) -> &IO {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut IO {
+        self.inner.get_mut()
+    }
+
+    /// Data read from the inner stream but not yet consumed.
+    pub fn buffer(&self) -> &[u8] {
+        self.inner.buffer()
+    }
+
+    /// Returns the inner stream, dropping any buffered data. Check [`Self::buffer`] first
+    /// if the remaining bytes matter.
+    pub fn into_inner(self) -> IO {
+        self.inner.into_inner()
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for BufTlsStream<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncBufRead for BufTlsStream<IO> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.get_mut().inner).consume(amt)
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> AsyncWrite for BufTlsStream<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
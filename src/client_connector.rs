@@ -1,62 +1,551 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use tokio::io::AsyncWriteExt;
 use tokio_rustls::{
     rustls::pki_types::{DnsName, ServerName},
+    rustls::{ClientConfig, ServerConfig},
     TlsAcceptor, TlsConnector,
 };
 
-use crate::{async_io::AsyncIO, tls_streams::{ClientStream, ServerStream}};
+use crate::{
+    async_io::AsyncIO,
+    error::TlsError,
+    observer::{observe_accept, observe_connect, HandshakeObserver},
+    peek_io::{PeekedIO, DEFAULT_PEEK_BUFFER},
+    tls_setup::{ClientTls, ServerTls},
+    tls_streams::{ClientStream, ServerStream},
+    ClientTlsStream, ServerTlsStream,
+};
+
+const TLS_HANDSHAKE_RECORD: u8 = 0x16;
+
+/// rustls' own default for how much outgoing data a TLS stream buffers.
+pub const DEFAULT_WRITE_BUFFER_LIMIT: usize = 64 * 1024;
 
+/// Cheap to clone: clones share the one `Arc`'d [`ClientConfig`] and session cache, so
+/// build a connector once and clone it per connection.
 #[derive(Clone)]
 pub struct ClientConnector {
     tls_connector: Option<(ServerName<'static>, TlsConnector)>,
+    observer: Option<Arc<dyn HandshakeObserver>>,
+    source: Option<Arc<ClientTls>>,
+    required_alpn: Option<Arc<Vec<Vec<u8>>>>,
+    write_buffer_limit: usize,
 }
 
 impl ClientConnector {
-    pub fn tls(name: &str, connector: TlsConnector) -> Result<Self, std::io::Error> {
-        let domain = DnsName::try_from(name)
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid dnsname"))?
-            .to_owned();
+    /// `name` is used for SNI and certificate verification only, it does not need to match
+    /// the address the stream is connected to.
+    pub fn tls(name: impl IntoServerName, connector: TlsConnector) -> Result<Self, std::io::Error> {
+        Ok(Self::from_server_name(name.into_server_name()?, connector))
+    }
 
-        Ok(ClientConnector {
-            tls_connector: Some((ServerName::DnsName(domain), connector)),
-        })
+    /// Like [`ClientConnector::tls`] but without sending the SNI extension, for servers that
+    /// reject it. The certificate is still verified against `expected_name`.
+    pub fn tls_no_sni(
+        expected_name: impl IntoServerName,
+        connector: TlsConnector,
+    ) -> Result<Self, std::io::Error> {
+        let mut config = ClientConfig::clone(connector.config());
+        config.enable_sni = false;
+
+        Self::tls(expected_name, TlsConnector::from(Arc::new(config)))
+    }
+
+    pub fn tls_ip(addr: IpAddr, connector: TlsConnector) -> Self {
+        Self::from_server_name(ServerName::IpAddress(addr.into()), connector)
+    }
+
+    /// Use an already validated `name`, skipping the parsing done by [`ClientConnector::tls`].
+    pub fn from_server_name(name: ServerName<'static>, connector: TlsConnector) -> Self {
+        ClientConnector {
+            tls_connector: Some((name, connector)),
+            observer: None,
+            source: None,
+            required_alpn: None,
+            write_buffer_limit: DEFAULT_WRITE_BUFFER_LIMIT,
+        }
+    }
+
+    /// Build the connector from a setup type. Unlike the other constructors this keeps
+    /// `tls` so [`ClientConnector::with_added_ca`] can rebuild the config later.
+    pub fn from_client_tls(
+        name: impl IntoServerName,
+        tls: impl Into<ClientTls>,
+    ) -> Result<Self, TlsError> {
+        let tls = tls.into();
+        let mut connector = Self::tls(name, tls.clone().into_connector()?)?;
+        connector.source = Some(Arc::new(tls));
+        Ok(connector)
+    }
+
+    /// A new connector whose config also trusts the CAs in `ca_pem`, e.g. an internal CA
+    /// learned at runtime. `self`, its clones and the connections they already made keep
+    /// the old trust set. Only connectors made with [`ClientConnector::from_client_tls`]
+    /// can be rebuilt, plain connectors are returned unchanged.
+    pub fn with_added_ca(&self, ca_pem: &[u8]) -> Result<ClientConnector, TlsError> {
+        let Some((name, _)) = &self.tls_connector else {
+            return Ok(self.clone());
+        };
+        let Some(source) = &self.source else {
+            return Err(TlsError::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "connector was not built with ClientConnector::from_client_tls",
+            )));
+        };
+
+        let mut tls = ClientTls::clone(source);
+        tls.add_ca_pem(ca_pem)?;
+        let mut connector = Self::from_client_tls(name.clone(), tls)?;
+        connector.observer = self.observer.clone();
+        connector.required_alpn = self.required_alpn.clone();
+        connector.write_buffer_limit = self.write_buffer_limit;
+        Ok(connector)
     }
 
     pub fn plain() -> Self {
         ClientConnector {
             tls_connector: None,
+            observer: None,
+            source: None,
+            required_alpn: None,
+            write_buffer_limit: DEFAULT_WRITE_BUFFER_LIMIT,
         }
     }
 
+    pub fn with_observer(mut self, observer: Arc<dyn HandshakeObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Fail TLS connections with `InvalidData` unless the server agreed to one of
+    /// `protocols`. The connector's config must offer them, see `with_alpn` on the setup
+    /// types. Plaintext connections are not checked.
+    pub fn require_alpn(mut self, protocols: &[&[u8]]) -> Self {
+        let protocols = protocols.iter().map(|protocol| protocol.to_vec()).collect();
+        self.required_alpn = Some(Arc::new(protocols));
+        self
+    }
+
+    /// How much outgoing data, plaintext waiting to be encrypted plus encrypted records
+    /// waiting for the socket, each TLS stream buffers before writes return `Pending`.
+    /// Larger limits can help bulk transfers at the cost of memory per connection. Reads
+    /// have no equivalent, rustls always reads up to 4 KiB from the socket at a time.
+    pub fn with_write_buffer_limit(mut self, limit: usize) -> Self {
+        self.write_buffer_limit = limit;
+        self
+    }
+
+    /// The config shared by this connector and its clones, `None` for plaintext.
+    pub fn client_config(&self) -> Option<&Arc<ClientConfig>> {
+        self.tls_connector.as_ref().map(|(_, connector)| connector.config())
+    }
+
     pub async fn connect<IO: AsyncIO>(&self, io: IO) -> Result<ClientStream<IO>, std::io::Error> {
+        let stream = match &self.tls_connector {
+            Some((name, connector)) => self.tls_stream(
+                observe_connect(self.observer.as_ref(), connector, name.clone(), io).await?,
+            ),
+            None => ClientStream::TcpStream(io),
+        };
+
+        self.check_alpn(&stream)?;
+        Ok(stream)
+    }
+
+    /// Connect and send `data` as TLS 1.3 0-RTT early data if a resumable session for the
+    /// server is cached, otherwise `data` is written once the handshake completes.
+    ///
+    /// Early data is not protected against replay: an attacker can capture it and send it
+    /// to the server again. Only use this for requests that are safe to process more than
+    /// once. Requires a connector built with `with_early_data`.
+    pub async fn connect_early<IO: AsyncIO>(
+        &self,
+        io: IO,
+        data: &[u8],
+    ) -> Result<ClientStream<IO>, std::io::Error> {
+        let mut stream = match &self.tls_connector {
+            Some((name, connector)) => {
+                if !connector.config().enable_early_data {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "early data not enabled on client config",
+                    ));
+                }
+
+                let connector = connector.clone().early_data(true);
+                let observer = self.observer.as_ref();
+                self.tls_stream(observe_connect(observer, &connector, name.clone(), io).await?)
+            }
+            None => ClientStream::TcpStream(io),
+        };
+
+        stream.write_all(data).await?;
+        stream.flush().await?;
+        self.check_alpn(&stream)?;
+        Ok(stream)
+    }
+
+    /// Connect using `name` for SNI and certificate verification instead of the name this
+    /// connector was built with. Plain connectors ignore `name`.
+    pub async fn connect_with_name<IO: AsyncIO>(
+        &self,
+        name: &str,
+        io: IO,
+    ) -> Result<ClientStream<IO>, std::io::Error> {
         match &self.tls_connector {
-            Some((name, connector)) => Ok(ClientStream::TlsStream(
-                connector.connect(name.clone(), io).await?,
-            )),
+            Some((_, connector)) => {
+                let name = parse_server_name(name)?;
+                let stream = self.tls_stream(
+                    observe_connect(self.observer.as_ref(), connector, name, io).await?,
+                );
+                self.check_alpn(&stream)?;
+                Ok(stream)
+            }
             None => Ok(ClientStream::TcpStream(io)),
         }
     }
+
+    /// StartTLS: run the TLS handshake over a stream that has so far been used in
+    /// plaintext, e.g. after SMTP's `STARTTLS`. `name` is used for SNI and verification.
+    /// Fails with `InvalidInput` if the stream already uses TLS or this connector is plain.
+    pub async fn upgrade<IO: AsyncIO>(
+        &self,
+        stream: ClientStream<IO>,
+        name: impl IntoServerName,
+    ) -> Result<ClientStream<IO>, std::io::Error> {
+        let Some((_, connector)) = &self.tls_connector else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "cannot upgrade with a plaintext connector",
+            ));
+        };
+        let io = match stream {
+            ClientStream::TcpStream(io) => io,
+            ClientStream::TlsStream(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "stream is already using tls",
+                ))
+            }
+        };
+
+        let name = name.into_server_name()?;
+        let stream = self.tls_stream(
+            observe_connect(self.observer.as_ref(), connector, name, io).await?,
+        );
+        self.check_alpn(&stream)?;
+        Ok(stream)
+    }
+
+    pub async fn connect_with_timeout<IO: AsyncIO>(
+        &self,
+        io: IO,
+        timeout: Duration,
+    ) -> Result<ClientStream<IO>, std::io::Error> {
+        if self.tls_connector.is_none() {
+            return self.connect(io).await;
+        }
+
+        match tokio::time::timeout(timeout, self.connect(io)).await {
+            Ok(result) => result,
+            Err(_) => Err(handshake_timeout()),
+        }
+    }
+
+    fn tls_stream<IO: AsyncIO>(&self, mut stream: ClientTlsStream<IO>) -> ClientStream<IO> {
+        stream.get_mut().1.set_buffer_limit(Some(self.write_buffer_limit));
+        ClientStream::TlsStream(stream)
+    }
+
+    fn check_alpn<IO: AsyncIO>(&self, stream: &ClientStream<IO>) -> Result<(), std::io::Error> {
+        let Some(required) = &self.required_alpn else {
+            return Ok(());
+        };
+        if stream.is_plaintext() {
+            return Ok(());
+        }
+
+        match stream.alpn_protocol() {
+            Some(protocol) if required.iter().any(|p| p == protocol) => Ok(()),
+            negotiated => {
+                let negotiated = negotiated.map(String::from_utf8_lossy);
+                tracing::error!(?negotiated, "server did not agree to a required ALPN protocol");
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "server negotiated ALPN {:?}, expected one of {:?}",
+                        negotiated,
+                        required.iter().map(|p| String::from_utf8_lossy(p)).collect::<Vec<_>>()
+                    ),
+                ))
+            }
+        }
+    }
 }
 
+/// Cheap to clone: clones share the one `Arc`'d [`ServerConfig`], so build the acceptor
+/// once and clone it into each connection task instead of rebuilding it.
 #[derive(Clone)]
 pub struct ClientAcceptor {
     tls_acceptor: Option<TlsAcceptor>,
+    observer: Option<Arc<dyn HandshakeObserver>>,
+    write_buffer_limit: usize,
 }
 
 impl ClientAcceptor {
     pub fn tls(acceptor: TlsAcceptor) -> Self {
         ClientAcceptor {
             tls_acceptor: Some(acceptor),
+            observer: None,
+            write_buffer_limit: DEFAULT_WRITE_BUFFER_LIMIT,
         }
     }
 
+    /// Build the server config once, see [`ServerTls::into_acceptor`].
+    pub fn from_server_tls(tls: impl Into<ServerTls>) -> Result<Self, TlsError> {
+        Ok(Self::tls(tls.into().into_acceptor()?))
+    }
+
     pub fn plain() -> Self {
-        ClientAcceptor { tls_acceptor: None }
+        ClientAcceptor {
+            tls_acceptor: None,
+            observer: None,
+            write_buffer_limit: DEFAULT_WRITE_BUFFER_LIMIT,
+        }
+    }
+
+    pub fn with_observer(mut self, observer: Arc<dyn HandshakeObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// See [`ClientConnector::with_write_buffer_limit`].
+    pub fn with_write_buffer_limit(mut self, limit: usize) -> Self {
+        self.write_buffer_limit = limit;
+        self
+    }
+
+    /// The config shared by this acceptor and its clones, `None` for plaintext.
+    pub fn server_config(&self) -> Option<&Arc<ServerConfig>> {
+        self.tls_acceptor.as_ref().map(|acceptor| acceptor.config())
     }
 
     pub async fn accept<IO: AsyncIO>(&self, io: IO) -> Result<ServerStream<IO>, std::io::Error> {
         match &self.tls_acceptor {
-            Some(acceptor) => Ok(ServerStream::TlsStream(acceptor.accept(io).await?)),
+            Some(acceptor) => {
+                Ok(self.tls_stream(observe_accept(self.observer.as_ref(), acceptor, io).await?))
+            }
             None => Ok(ServerStream::TcpStream(io)),
         }
     }
+
+    /// Accept TLS and plaintext on the same port, e.g. plain HTTP health checks on a TLS
+    /// listener. The first byte is peeked and a TLS handshake is only run if it starts a TLS
+    /// handshake record, otherwise the plaintext stream still yields the peeked byte.
+    pub async fn accept_detect<IO: AsyncIO>(
+        &self,
+        io: IO,
+    ) -> Result<ServerStream<PeekedIO<IO>>, std::io::Error> {
+        self.accept_detect_with_peek_buffer(io, DEFAULT_PEEK_BUFFER).await
+    }
+
+    /// Same as [`ClientAcceptor::accept_detect`].
+    pub async fn accept_or_plain<IO: AsyncIO>(
+        &self,
+        io: IO,
+    ) -> Result<ServerStream<PeekedIO<IO>>, std::io::Error> {
+        self.accept_detect(io).await
+    }
+
+    /// Like [`ClientAcceptor::accept_detect`] with the size of the first read, see
+    /// [`PeekedIO::with_capacity`] for the memory vs partial read tradeoff.
+    pub async fn accept_detect_with_peek_buffer<IO: AsyncIO>(
+        &self,
+        io: IO,
+        peek_buffer: usize,
+    ) -> Result<ServerStream<PeekedIO<IO>>, std::io::Error> {
+        let mut io = PeekedIO::with_capacity(io, peek_buffer);
+        let first = io.peek_byte().await?;
+
+        match &self.tls_acceptor {
+            Some(acceptor) if first == Some(TLS_HANDSHAKE_RECORD) => {
+                Ok(self.tls_stream(observe_accept(self.observer.as_ref(), acceptor, io).await?))
+            }
+            _ => Ok(ServerStream::TcpStream(io)),
+        }
+    }
+
+    /// StartTLS: run the TLS handshake over a stream that has so far been used in
+    /// plaintext, e.g. after replying to SMTP's `STARTTLS`. Upgrade exactly at the protocol
+    /// boundary: the handshake reads straight from the inner IO, so anything the caller
+    /// buffered past the command (such as an early ClientHello) is lost. Fails with
+    /// `InvalidInput` if the stream already uses TLS or this acceptor is plain.
+    pub async fn upgrade<IO: AsyncIO>(
+        &self,
+        stream: ServerStream<IO>,
+    ) -> Result<ServerStream<IO>, std::io::Error> {
+        let Some(acceptor) = &self.tls_acceptor else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "cannot upgrade with a plaintext acceptor",
+            ));
+        };
+        let io = match stream {
+            ServerStream::TcpStream(io) => io,
+            ServerStream::TlsStream(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "stream is already using tls",
+                ))
+            }
+        };
+
+        Ok(self.tls_stream(observe_accept(self.observer.as_ref(), acceptor, io).await?))
+    }
+
+    /// Defer the handshake so the accept loop can hand the connection to a worker, which
+    /// then runs it with [`PendingAccept::handshake`].
+    pub fn accept_lazy<IO: AsyncIO>(&self, io: IO) -> PendingAccept<IO> {
+        PendingAccept {
+            acceptor: self.clone(),
+            io,
+        }
+    }
+
+    pub async fn accept_with_timeout<IO: AsyncIO>(
+        &self,
+        io: IO,
+        timeout: Duration,
+    ) -> Result<ServerStream<IO>, std::io::Error> {
+        if self.tls_acceptor.is_none() {
+            return self.accept(io).await;
+        }
+
+        match tokio::time::timeout(timeout, self.accept(io)).await {
+            Ok(result) => result,
+            Err(_) => Err(handshake_timeout()),
+        }
+    }
+
+    fn tls_stream<IO: AsyncIO>(&self, mut stream: ServerTlsStream<IO>) -> ServerStream<IO> {
+        stream.get_mut().1.set_buffer_limit(Some(self.write_buffer_limit));
+        ServerStream::TlsStream(stream)
+    }
+}
+
+pub struct PendingAccept<IO> {
+    acceptor: ClientAcceptor,
+    io: IO,
+}
+
+impl<IO: AsyncIO> PendingAccept<IO> {
+    /// Whether a TLS handshake still has to run before the stream is usable.
+    pub fn needs_handshake(&self) -> bool {
+        self.acceptor.tls_acceptor.is_some()
+    }
+
+    pub fn get_ref(&self) -> &IO {
+        &self.io
+    }
+
+    pub async fn handshake(self) -> Result<ServerStream<IO>, std::io::Error> {
+        self.acceptor.accept(self.io).await
+    }
+}
+
+#[derive(Clone)]
+pub struct ReloadableAcceptor {
+    config: Arc<ArcSwap<ServerConfig>>,
+}
+
+impl ReloadableAcceptor {
+    pub fn new(tls: ServerTls) -> Result<Self, TlsError> {
+        Ok(ReloadableAcceptor {
+            config: Arc::new(ArcSwap::from_pointee(tls.into_server_config()?)),
+        })
+    }
+
+    pub fn reload(&self, tls: ServerTls) -> Result<(), TlsError> {
+        let config = tls.into_server_config()?;
+        self.config.store(Arc::new(config));
+        tracing::info!("reloaded server tls config");
+        Ok(())
+    }
+
+    pub async fn accept<IO: AsyncIO>(&self, io: IO) -> Result<ServerStream<IO>, std::io::Error> {
+        let acceptor = TlsAcceptor::from(self.config.load_full());
+        Ok(ServerStream::TlsStream(acceptor.accept(io).await?))
+    }
+
+    pub async fn accept_with_timeout<IO: AsyncIO>(
+        &self,
+        io: IO,
+        timeout: Duration,
+    ) -> Result<ServerStream<IO>, std::io::Error> {
+        match tokio::time::timeout(timeout, self.accept(io)).await {
+            Ok(result) => result,
+            Err(_) => Err(handshake_timeout()),
+        }
+    }
+}
+
+/// Names accepted by [`ClientConnector::tls`]: a DNS name or IP address as a string, an
+/// [`IpAddr`], or an already built [`ServerName`].
+pub trait IntoServerName {
+    fn into_server_name(self) -> Result<ServerName<'static>, std::io::Error>;
+}
+
+impl IntoServerName for &str {
+    fn into_server_name(self) -> Result<ServerName<'static>, std::io::Error> {
+        parse_server_name(self)
+    }
+}
+
+impl IntoServerName for String {
+    fn into_server_name(self) -> Result<ServerName<'static>, std::io::Error> {
+        parse_server_name(&self)
+    }
+}
+
+impl IntoServerName for &String {
+    fn into_server_name(self) -> Result<ServerName<'static>, std::io::Error> {
+        parse_server_name(self)
+    }
+}
+
+impl IntoServerName for IpAddr {
+    fn into_server_name(self) -> Result<ServerName<'static>, std::io::Error> {
+        Ok(ServerName::IpAddress(self.into()))
+    }
+}
+
+impl IntoServerName for ServerName<'static> {
+    fn into_server_name(self) -> Result<ServerName<'static>, std::io::Error> {
+        Ok(self)
+    }
+}
+
+fn parse_server_name(name: &str) -> Result<ServerName<'static>, std::io::Error> {
+    if let Ok(addr) = name.parse::<IpAddr>() {
+        return Ok(ServerName::IpAddress(addr.into()));
+    }
+
+    let domain = DnsName::try_from(name)
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid dnsname: {:?}", name),
+            )
+        })?
+        .to_owned();
+
+    Ok(ServerName::DnsName(domain))
+}
+
+fn handshake_timeout() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::TimedOut, "tls handshake timed out")
 }
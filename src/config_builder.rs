@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use tokio_rustls::rustls::{ClientConfig, ServerConfig, SupportedProtocolVersion};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use crate::error::TlsError;
+use crate::tls_setup::{ClientTls, ServerTls};
+
+/// Collects config options for a setup type, e.g. [`crate::tls_setup::MutualTls`], and
+/// finishes with [`build_connector`] or [`build_acceptor`]. Options set here replace the
+/// same options set through the setup type's `with_*` methods.
+///
+/// [`build_connector`]: TlsConfigBuilder::build_connector
+/// [`build_acceptor`]: TlsConfigBuilder::build_acceptor
+pub struct TlsConfigBuilder<T> {
+    tls: T,
+    alpn: Option<Vec<Vec<u8>>>,
+    protocol_versions: Option<Vec<&'static SupportedProtocolVersion>>,
+    session_cache: Option<usize>,
+    #[cfg(feature = "key-log")]
+    key_log: bool,
+}
+
+impl<T> TlsConfigBuilder<T> {
+    pub fn new(tls: T) -> Self {
+        TlsConfigBuilder {
+            tls,
+            alpn: None,
+            protocol_versions: None,
+            session_cache: None,
+            #[cfg(feature = "key-log")]
+            key_log: false,
+        }
+    }
+
+    pub fn alpn(mut self, protocols: Vec<Vec<u8>>) -> Self {
+        self.alpn = Some(protocols);
+        self
+    }
+
+    pub fn protocol_versions(mut self, versions: &[&'static SupportedProtocolVersion]) -> Self {
+        self.protocol_versions = Some(versions.to_vec());
+        self
+    }
+
+    /// Only used by clients, servers keep no session cache of their own.
+    pub fn session_cache(mut self, capacity: usize) -> Self {
+        self.session_cache = Some(capacity);
+        self
+    }
+
+    /// See [`crate::tls_setup::MutualTls::with_key_log`], never enable in production.
+    #[cfg(feature = "key-log")]
+    pub fn key_log(mut self) -> Self {
+        self.key_log = true;
+        self
+    }
+}
+
+impl<T: Into<ClientTls>> TlsConfigBuilder<T> {
+    pub fn build_connector(self) -> Result<TlsConnector, TlsError> {
+        Ok(TlsConnector::from(Arc::new(self.build_client_config()?)))
+    }
+
+    pub fn build_client_config(self) -> Result<ClientConfig, TlsError> {
+        let mut tls = self.tls.into();
+        if let Some(protocols) = self.alpn {
+            tls = tls.with_alpn(protocols);
+        }
+        if let Some(versions) = &self.protocol_versions {
+            tls = tls.with_protocol_versions(versions);
+        }
+        if let Some(capacity) = self.session_cache {
+            tls = tls.with_session_cache(capacity);
+        }
+        #[cfg(feature = "key-log")]
+        if self.key_log {
+            tls = tls.with_key_log();
+        }
+
+        tls.into_client_config()
+    }
+}
+
+impl<T: Into<ServerTls>> TlsConfigBuilder<T> {
+    pub fn build_acceptor(self) -> Result<TlsAcceptor, TlsError> {
+        Ok(TlsAcceptor::from(Arc::new(self.build_server_config()?)))
+    }
+
+    pub fn build_server_config(self) -> Result<ServerConfig, TlsError> {
+        let mut tls = self.tls.into();
+        if let Some(protocols) = self.alpn {
+            tls = tls.with_alpn(protocols);
+        }
+        if let Some(versions) = &self.protocol_versions {
+            tls = tls.with_protocol_versions(versions);
+        }
+        #[cfg(feature = "key-log")]
+        if self.key_log {
+            tls = tls.with_key_log();
+        }
+
+        tls.into_server_config()
+    }
+}
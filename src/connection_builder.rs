@@ -1,10 +1,15 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsConnector;
 
 use crate::async_io::AsyncIO;
-use crate::client_connector::ClientConnector;
-use crate::tls_streams::ClientStream;
+use crate::client_connector::{ClientAcceptor, ClientConnector, ReloadableAcceptor};
+use crate::tls_streams::{ClientStream, ServerStream};
 
 pub trait ConnectionBuilder: Sync + Send + 'static {
     type IO: AsyncIO;
@@ -21,3 +26,618 @@ impl ConnectionBuilder for ClientConnector {
     }
 }
 
+pub trait AcceptBuilder: Sync + Send + 'static {
+    type IO: AsyncIO;
+
+    fn accept(&self, stream: TcpStream) -> impl std::future::Future<Output = std::io::Result<Self::IO>> + Send;
+}
+
+impl AcceptBuilder for ClientAcceptor {
+    type IO = ServerStream<TcpStream>;
+
+    async fn accept(&self, stream: TcpStream) -> std::io::Result<Self::IO> {
+        self.accept(stream).await
+    }
+}
+
+impl AcceptBuilder for ReloadableAcceptor {
+    type IO = ServerStream<TcpStream>;
+
+    async fn accept(&self, stream: TcpStream) -> std::io::Result<Self::IO> {
+        self.accept(stream).await
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SocketOptions {
+    pub nodelay: bool,
+    pub keepalive: Option<Duration>,
+}
+
+impl SocketOptions {
+    pub fn apply(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+
+        if let Some(keepalive) = self.keepalive {
+            let keepalive = socket2::TcpKeepalive::new().with_time(keepalive);
+            socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ClientConnector {
+    pub async fn connect_with_options(
+        &self,
+        addr: SocketAddr,
+        options: &SocketOptions,
+    ) -> std::io::Result<ClientStream<TcpStream>> {
+        let stream = TcpStream::connect(addr).await?;
+        options.apply(&stream)?;
+        self.connect(stream).await
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: usize,
+    pub base_delay: Duration,
+    pub jitter: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            jitter: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let exponent = u32::try_from(attempt.saturating_sub(1)).unwrap_or(u32::MAX).min(16);
+        let mut delay = self.base_delay.saturating_mul(1 << exponent);
+
+        if let Some(jitter) = self.jitter {
+            let random = RandomState::new().build_hasher().finish();
+            let nanos = u64::try_from(jitter.as_nanos()).unwrap_or(u64::MAX);
+            if nanos != 0 {
+                delay += Duration::from_nanos(random % nanos);
+            }
+        }
+
+        delay
+    }
+}
+
+impl ClientConnector {
+    /// Retries the TCP connect on `ConnectionRefused` and `TimedOut` with exponential
+    /// backoff. TLS handshake failures are returned immediately.
+    pub async fn connect_with_retry(
+        &self,
+        addr: SocketAddr,
+        policy: &RetryPolicy,
+    ) -> std::io::Result<ClientStream<TcpStream>> {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let error = match TcpStream::connect(addr).await {
+                Ok(stream) => return self.connect(stream).await,
+                Err(error) => error,
+            };
+
+            let retryable = matches!(
+                error.kind(),
+                std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::TimedOut
+            );
+            if !retryable || attempt >= policy.max_attempts {
+                return Err(error);
+            }
+
+            let delay = policy.delay(attempt);
+            tracing::warn!(?error, %addr, attempt, ?delay, "connect failed, retrying");
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Which TLS handshake failures make [`ClientConnector::connect_with_plaintext_fallback`]
+/// reconnect without TLS. Certificate verification failures never fall back.
+#[derive(Clone, Copy, Debug)]
+pub struct PlaintextFallback {
+    pub on_invalid_message: bool,
+    pub on_closed: bool,
+}
+
+impl Default for PlaintextFallback {
+    fn default() -> Self {
+        PlaintextFallback {
+            on_invalid_message: true,
+            on_closed: true,
+        }
+    }
+}
+
+impl PlaintextFallback {
+    fn should_fallback(&self, error: &std::io::Error) -> bool {
+        if let Some(tls_error) = error.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>()) {
+            return matches!(tls_error, rustls::Error::InvalidMessage(_)) && self.on_invalid_message;
+        }
+
+        matches!(
+            error.kind(),
+            std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::ConnectionReset
+        ) && self.on_closed
+    }
+}
+
+impl ClientConnector {
+    /// Try TLS first and if the peer does not speak TLS reconnect on a new socket and
+    /// return a plaintext stream. Intended for migrating peers onto TLS.
+    pub async fn connect_with_plaintext_fallback(
+        &self,
+        addr: SocketAddr,
+        fallback: PlaintextFallback,
+    ) -> std::io::Result<ClientStream<TcpStream>> {
+        let stream = TcpStream::connect(addr).await?;
+        let error = match self.connect(stream).await {
+            Ok(stream) => return Ok(stream),
+            Err(error) => error,
+        };
+
+        if !fallback.should_fallback(&error) {
+            return Err(error);
+        }
+
+        tracing::warn!(?error, %addr, "tls handshake failed, falling back to plaintext");
+        Ok(ClientStream::TcpStream(TcpStream::connect(addr).await?))
+    }
+}
+
+const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+impl ClientConnector {
+    pub async fn connect_happy_eyeballs(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> std::io::Result<ClientStream<TcpStream>> {
+        let addrs = interleave_families(tokio::net::lookup_host((host, port)).await?.collect());
+        let mut remaining = addrs.into_iter().peekable();
+        let mut attempts = JoinSet::new();
+        let mut last_error = None;
+
+        loop {
+            if let Some(addr) = remaining.next() {
+                attempts.spawn(TcpStream::connect(addr));
+            }
+
+            if attempts.is_empty() {
+                break;
+            }
+
+            let has_more = remaining.peek().is_some();
+            let delay = tokio::time::sleep(HAPPY_EYEBALLS_DELAY);
+            tokio::pin!(delay);
+
+            while !attempts.is_empty() {
+                tokio::select! {
+                    _ = &mut delay, if has_more => break,
+                    joined = attempts.join_next() => {
+                        let result = match joined {
+                            Some(Ok(result)) => result,
+                            Some(Err(error)) => Err(std::io::Error::other(error)),
+                            None => break,
+                        };
+
+                        match result {
+                            Ok(stream) => {
+                                attempts.abort_all();
+                                return self.connect(stream).await;
+                            }
+                            Err(error) => {
+                                tracing::warn!(?error, "connection attempt failed");
+                                last_error = Some(error);
+
+                                if has_more {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses found for host")
+        }))
+    }
+}
+
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+
+    let prefer_v6 = first.is_ipv6();
+    let (mut preferred, mut other): (Vec<SocketAddr>, Vec<SocketAddr>) =
+        addrs.iter().partition(|addr| addr.is_ipv6() == prefer_v6);
+    preferred.reverse();
+    other.reverse();
+
+    let mut result = Vec::with_capacity(addrs.len());
+    loop {
+        match (preferred.pop(), other.pop()) {
+            (None, None) => break,
+            (a, b) => result.extend(a.into_iter().chain(b)),
+        }
+    }
+    result
+}
+
+#[derive(Clone)]
+pub struct HostConnectionBuilder {
+    tls_connector: Option<TlsConnector>,
+    server_name: Option<String>,
+}
+
+impl HostConnectionBuilder {
+    pub fn tls(connector: TlsConnector) -> Self {
+        HostConnectionBuilder {
+            tls_connector: Some(connector),
+            server_name: None,
+        }
+    }
+
+    pub fn plain() -> Self {
+        HostConnectionBuilder {
+            tls_connector: None,
+            server_name: None,
+        }
+    }
+
+    /// Send `name` as SNI and verify the certificate against it instead of the host in the
+    /// dialed target, e.g. when connecting to a load balancer IP serving `api.internal`.
+    pub fn with_server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.to_string());
+        self
+    }
+
+    pub async fn connect(&self, target: &str) -> std::io::Result<ClientStream<TcpStream>> {
+        let host = match &self.server_name {
+            Some(name) => name.as_str(),
+            None => target_host(target)?,
+        };
+        let client = match &self.tls_connector {
+            Some(connector) => ClientConnector::tls(host, connector.clone())?,
+            None => ClientConnector::plain(),
+        };
+
+        let mut last_error = None;
+        for addr in tokio::net::lookup_host(target).await? {
+            match ConnectionBuilder::connect(&client, addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(error) => {
+                    tracing::warn!(?error, %addr, "failed to connect to address");
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses found for host")
+        }))
+    }
+}
+
+fn target_host(target: &str) -> std::io::Result<&str> {
+    let Some((host, _port)) = target.rsplit_once(':') else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "target must be in host:port form",
+        ));
+    };
+
+    Ok(host.trim_start_matches('[').trim_end_matches(']'))
+}
+
+const MAX_PROXY_RESPONSE: usize = 8192;
+
+/// Dials `addr` through an HTTP proxy: connects to the proxy, asks it to open a tunnel with
+/// `CONNECT`, then runs the [`ClientConnector`] over the tunnel.
+#[derive(Clone)]
+pub struct ProxyConnectionBuilder {
+    proxy: SocketAddr,
+    connector: ClientConnector,
+    authorization: Option<String>,
+}
+
+impl ProxyConnectionBuilder {
+    pub fn new(proxy: SocketAddr, connector: ClientConnector) -> Self {
+        ProxyConnectionBuilder {
+            proxy,
+            connector,
+            authorization: None,
+        }
+    }
+
+    /// Sent as the `Proxy-Authorization` header, e.g. `Basic dXNlcjpwYXNz`.
+    pub fn with_authorization(mut self, value: &str) -> std::io::Result<Self> {
+        if value.contains(['\r', '\n']) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid proxy authorization",
+            ));
+        }
+
+        self.authorization = Some(value.to_string());
+        Ok(self)
+    }
+
+    /// Tunnel to `target` in `host:port` form, letting the proxy resolve the host.
+    pub async fn connect_host(&self, target: &str) -> std::io::Result<ClientStream<TcpStream>> {
+        target_host(target)?;
+        let stream = self.tunnel(target).await?;
+        self.connector.connect(stream).await
+    }
+
+    async fn tunnel(&self, target: &str) -> std::io::Result<TcpStream> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        if target.contains(['\r', '\n']) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid proxy target",
+            ));
+        }
+
+        let mut stream = TcpStream::connect(self.proxy).await?;
+
+        let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+        if let Some(authorization) = &self.authorization {
+            request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read byte by byte so nothing after the headers is taken from the tunnel.
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= MAX_PROXY_RESPONSE {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "proxy response headers too large",
+                ));
+            }
+            response.push(stream.read_u8().await?);
+        }
+
+        let status_line = response.split(|b| *b == b'\r').next().unwrap_or_default();
+        let status_line = String::from_utf8_lossy(status_line);
+        let mut parts = status_line.split_whitespace();
+        let status = match (parts.next(), parts.next()) {
+            (Some(version), Some(code)) if version.starts_with("HTTP/1.") => {
+                code.parse::<u16>().ok()
+            }
+            _ => None,
+        };
+
+        match status {
+            Some(200..=299) => Ok(stream),
+            Some(status) => {
+                tracing::warn!(proxy = %self.proxy, target, status, "proxy refused CONNECT");
+                let kind = if status == 407 {
+                    std::io::ErrorKind::PermissionDenied
+                } else {
+                    std::io::ErrorKind::ConnectionRefused
+                };
+                Err(std::io::Error::new(kind, format!("proxy CONNECT failed: {}", status_line)))
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid proxy response: {:?}", status_line),
+            )),
+        }
+    }
+}
+
+impl ConnectionBuilder for ProxyConnectionBuilder {
+    type IO = ClientStream<TcpStream>;
+
+    async fn connect(&self, addr: SocketAddr) -> std::io::Result<Self::IO> {
+        let stream = self.tunnel(&addr.to_string()).await?;
+        self.connector.connect(stream).await
+    }
+}
+
+/// Dials `addr` through a SOCKS5 proxy, then runs the [`ClientConnector`] over the tunnel.
+/// SNI and certificate checks use the connector's server name, not the proxy.
+#[derive(Clone)]
+pub struct Socks5ConnectionBuilder {
+    proxy: SocketAddr,
+    connector: ClientConnector,
+    credentials: Option<(String, String)>,
+}
+
+enum Socks5Target<'a> {
+    Addr(SocketAddr),
+    Host(&'a str, u16),
+}
+
+impl Socks5ConnectionBuilder {
+    pub fn new(proxy: SocketAddr, connector: ClientConnector) -> Self {
+        Socks5ConnectionBuilder {
+            proxy,
+            connector,
+            credentials: None,
+        }
+    }
+
+    /// Authenticate with the proxy using username/password auth (RFC 1929).
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Tunnel to `target` in `host:port` form, letting the proxy resolve the host.
+    pub async fn connect_host(&self, target: &str) -> std::io::Result<ClientStream<TcpStream>> {
+        let host = target_host(target)?;
+        let port = target
+            .rsplit_once(':')
+            .and_then(|(_, port)| port.parse::<u16>().ok())
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid port in target")
+            })?;
+
+        let target = match host.parse() {
+            Ok(ip) => Socks5Target::Addr(SocketAddr::new(ip, port)),
+            Err(_) => Socks5Target::Host(host, port),
+        };
+        let stream = self.tunnel(target).await?;
+        self.connector.connect(stream).await
+    }
+
+    async fn tunnel(&self, target: Socks5Target<'_>) -> std::io::Result<TcpStream> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = TcpStream::connect(self.proxy).await?;
+
+        let greeting: &[u8] = match self.credentials {
+            Some(_) => &[5, 2, 0, 2],
+            None => &[5, 1, 0],
+        };
+        stream.write_all(greeting).await?;
+
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        match (reply, &self.credentials) {
+            ([5, 0], _) => {}
+            ([5, 2], Some((username, password))) => {
+                let (Ok(username_len), Ok(password_len)) =
+                    (u8::try_from(username.len()), u8::try_from(password.len()))
+                else {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "socks5 username and password must be at most 255 bytes",
+                    ));
+                };
+
+                let mut auth = vec![1, username_len];
+                auth.extend_from_slice(username.as_bytes());
+                auth.push(password_len);
+                auth.extend_from_slice(password.as_bytes());
+                stream.write_all(&auth).await?;
+
+                stream.read_exact(&mut reply).await?;
+                if reply[0] != 1 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("invalid socks5 auth reply version: {}", reply[0]),
+                    ));
+                }
+                if reply[1] != 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::PermissionDenied,
+                        "socks5 proxy rejected credentials",
+                    ));
+                }
+            }
+            ([5, 0xff], _) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "socks5 proxy accepted none of the offered auth methods",
+                ));
+            }
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid socks5 method selection: {:?}", reply),
+                ));
+            }
+        }
+
+        let mut request = vec![5, 1, 0];
+        let port = match target {
+            Socks5Target::Addr(SocketAddr::V4(addr)) => {
+                request.push(1);
+                request.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            Socks5Target::Addr(SocketAddr::V6(addr)) => {
+                request.push(4);
+                request.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            Socks5Target::Host(host, port) => {
+                let Ok(len) = u8::try_from(host.len()) else {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "socks5 host name must be at most 255 bytes",
+                    ));
+                };
+                request.push(3);
+                request.push(len);
+                request.extend_from_slice(host.as_bytes());
+                port
+            }
+        };
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != 5 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid socks5 reply version {}", reply[0]),
+            ));
+        }
+
+        if reply[1] != 0 {
+            let (kind, reason) = match reply[1] {
+                2 => (std::io::ErrorKind::PermissionDenied, "connection not allowed by ruleset"),
+                3 => (std::io::ErrorKind::NetworkUnreachable, "network unreachable"),
+                4 => (std::io::ErrorKind::HostUnreachable, "host unreachable"),
+                5 => (std::io::ErrorKind::ConnectionRefused, "connection refused"),
+                6 => (std::io::ErrorKind::TimedOut, "ttl expired"),
+                7 => (std::io::ErrorKind::Unsupported, "command not supported"),
+                8 => (std::io::ErrorKind::Unsupported, "address type not supported"),
+                _ => (std::io::ErrorKind::Other, "general failure"),
+            };
+            tracing::warn!(proxy = %self.proxy, code = reply[1], reason, "socks5 connect failed");
+            return Err(std::io::Error::new(kind, format!("socks5 connect failed: {}", reason)));
+        }
+
+        // The bound address is not needed, skip it so the tunnel starts clean.
+        let bound_len = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => usize::from(stream.read_u8().await?),
+            atyp => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid socks5 address type {}", atyp),
+                ));
+            }
+        };
+        let mut bound = vec![0u8; bound_len + 2];
+        stream.read_exact(&mut bound).await?;
+
+        Ok(stream)
+    }
+}
+
+impl ConnectionBuilder for Socks5ConnectionBuilder {
+    type IO = ClientStream<TcpStream>;
+
+    async fn connect(&self, addr: SocketAddr) -> std::io::Result<Self::IO> {
+        let stream = self.tunnel(Socks5Target::Addr(addr)).await?;
+        self.connector.connect(stream).await
+    }
+}
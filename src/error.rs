@@ -0,0 +1,110 @@
+use std::fmt::{Display, Formatter};
+
+use tokio_rustls::rustls::server::VerifierBuilderError;
+
+#[derive(Debug)]
+pub enum TlsError {
+    Io(std::io::Error),
+    NoCertificates,
+    NoPrivateKey,
+    MultiplePrivateKeys(usize),
+    InvalidCa,
+    InvalidChain(String),
+    InvalidCrl(String),
+    InvalidKey(String),
+    KeyMismatch,
+    UnsupportedKeyAlgorithm(String),
+    UnsupportedKeyEncryption(String),
+    WrongPassword,
+    Pkcs12(String),
+    NoCipherSuites,
+    NoCryptoProvider,
+    InvalidFragmentSize(usize),
+    ClientVerifier(VerifierBuilderError),
+    Rustls(rustls::Error),
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::NoCertificates => write!(f, "no certificates in file"),
+            Self::NoPrivateKey => write!(f, "could not find valid private key"),
+            Self::MultiplePrivateKeys(count) => write!(
+                f,
+                "found {} private keys, expected exactly one per PEM file",
+                count
+            ),
+            Self::InvalidCa => write!(f, "no CA certificate found"),
+            Self::InvalidChain(reason) => write!(f, "invalid certificate chain: {}", reason),
+            Self::InvalidCrl(reason) => {
+                write!(f, "invalid certificate revocation list: {}", reason)
+            }
+            Self::InvalidKey(reason) => write!(f, "invalid private key: {}", reason),
+            Self::KeyMismatch => write!(f, "private key does not match certificate"),
+            Self::UnsupportedKeyAlgorithm(algorithm) => write!(
+                f,
+                "{} private keys are not supported by the crypto provider",
+                algorithm
+            ),
+            Self::UnsupportedKeyEncryption(scheme) => write!(
+                f,
+                "unsupported private key encryption {}, convert the key to PKCS#8 with \
+                 `openssl pkcs8 -topk8`",
+                scheme
+            ),
+            Self::WrongPassword => write!(f, "failed to decrypt private key, wrong password?"),
+            Self::Pkcs12(reason) => write!(f, "failed to read pkcs12 archive: {}", reason),
+            Self::NoCipherSuites => write!(f, "no cipher suites selected"),
+            Self::NoCryptoProvider => write!(
+                f,
+                "no rustls crypto provider installed, call tls_friend::install_crypto() first"
+            ),
+            Self::InvalidFragmentSize(size) => write!(
+                f,
+                "max fragment size {} is outside the allowed range 32..=16384",
+                size
+            ),
+            Self::ClientVerifier(error) => write!(f, "invalid client verifier: {}", error),
+            Self::Rustls(error) => write!(f, "rustls error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::ClientVerifier(error) => Some(error),
+            Self::Rustls(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TlsError {
+    fn from(value: std::io::Error) -> Self {
+        TlsError::Io(value)
+    }
+}
+
+impl From<rustls::Error> for TlsError {
+    fn from(value: rustls::Error) -> Self {
+        TlsError::Rustls(value)
+    }
+}
+
+impl From<TlsError> for std::io::Error {
+    fn from(value: TlsError) -> Self {
+        match value {
+            TlsError::Io(error) => error,
+            TlsError::NoCipherSuites
+            | TlsError::InvalidFragmentSize(_)
+            | TlsError::Rustls(_) => {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, value)
+            }
+            TlsError::NoCryptoProvider => std::io::Error::other(value),
+            other => std::io::Error::new(std::io::ErrorKind::InvalidData, other),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use base64ct::Encoding;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockCipher, BlockDecryptMut, KeyInit, KeyIvInit};
+use md5::{Digest, Md5};
+use tokio_rustls::rustls::pki_types::{
+    PrivateKeyDer, PrivatePkcs1KeyDer, PrivatePkcs8KeyDer, PrivateSec1KeyDer,
+};
+
+use crate::error::TlsError;
+
+/// Decrypt a key in the traditional OpenSSL format, where the PEM block carries
+/// `Proc-Type: 4,ENCRYPTED` and `DEK-Info` headers.
+pub(crate) fn parse_key_encrypted(
+    bytes: &[u8],
+    password: &str,
+) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| TlsError::InvalidKey("encrypted private key is not valid PEM".to_string()))?;
+    let mut lines = text.lines().map(str::trim);
+
+    let label = loop {
+        let Some(line) = lines.next() else {
+            return Err(TlsError::NoPrivateKey);
+        };
+        let label = line.strip_prefix("-----BEGIN ").and_then(|l| l.strip_suffix("-----"));
+        if let Some(label) = label.filter(|label| label.ends_with("PRIVATE KEY")) {
+            break label;
+        }
+    };
+
+    let mut dek_info = None;
+    let mut body = String::new();
+    for line in lines {
+        if line.starts_with("-----END ") {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name == "DEK-Info" {
+                dek_info = Some(value.trim());
+            }
+            continue;
+        }
+        body.push_str(line);
+    }
+
+    let Some((cipher, iv)) = dek_info.and_then(|info| info.split_once(',')) else {
+        return Err(TlsError::InvalidKey("missing DEK-Info header".to_string()));
+    };
+    let iv = decode_hex(iv)
+        .filter(|iv| iv.len() >= 8)
+        .ok_or_else(|| TlsError::InvalidKey("invalid DEK-Info IV".to_string()))?;
+    let encrypted = base64ct::Base64::decode_vec(&body)
+        .map_err(|error| TlsError::InvalidKey(format!("invalid encrypted key body: {}", error)))?;
+
+    let der = match cipher {
+        "AES-128-CBC" => decrypt::<aes::Aes128>(password, &iv, &encrypted)?,
+        "AES-192-CBC" => decrypt::<aes::Aes192>(password, &iv, &encrypted)?,
+        "AES-256-CBC" => decrypt::<aes::Aes256>(password, &iv, &encrypted)?,
+        "DES-EDE3-CBC" => decrypt::<des::TdesEde3>(password, &iv, &encrypted)?,
+        other => return Err(TlsError::UnsupportedKeyEncryption(other.to_string())),
+    };
+
+    match label {
+        "RSA PRIVATE KEY" => Ok(PrivateKeyDer::Pkcs1(PrivatePkcs1KeyDer::from(der))),
+        "EC PRIVATE KEY" => Ok(PrivateKeyDer::Sec1(PrivateSec1KeyDer::from(der))),
+        "PRIVATE KEY" => Ok(PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(der))),
+        other => Err(TlsError::UnsupportedKeyAlgorithm(other.to_string())),
+    }
+}
+
+fn decrypt<C>(password: &str, iv: &[u8], encrypted: &[u8]) -> Result<Vec<u8>, TlsError>
+where
+    C: BlockCipher + BlockDecryptMut + KeyInit,
+{
+    let key = evp_bytes_to_key(password.as_bytes(), &iv[..8], C::key_size());
+    let decryptor = cbc::Decryptor::<C>::new_from_slices(&key, iv)
+        .map_err(|_| TlsError::InvalidKey("invalid DEK-Info IV length".to_string()))?;
+
+    decryptor.decrypt_padded_vec_mut::<Pkcs7>(encrypted).map_err(|_| {
+        tracing::error!("failed to decrypt traditional encrypted private key");
+        TlsError::WrongPassword
+    })
+}
+
+/// OpenSSL's `EVP_BytesToKey` with MD5 and a single iteration, as used for these keys.
+fn evp_bytes_to_key(password: &[u8], salt: &[u8], key_len: usize) -> Vec<u8> {
+    let mut key = Vec::with_capacity(key_len);
+    let mut block: Vec<u8> = Vec::new();
+
+    while key.len() < key_len {
+        let mut hasher = Md5::new();
+        hasher.update(&block);
+        hasher.update(password);
+        hasher.update(salt);
+        block = hasher.finalize().to_vec();
+        key.extend_from_slice(&block);
+    }
+
+    key.truncate(key_len);
+    key
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -1,23 +1,120 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use rustls::crypto::CryptoProvider;
 
 pub mod async_io;
+pub mod buf_stream;
 pub mod client_connector;
+mod client_identity;
+pub mod config_builder;
 pub mod connection_builder;
+pub mod counting_stream;
+#[cfg(feature = "dangerous-insecure")]
+mod dangerous;
+pub mod error;
+#[cfg(feature = "legacy-encrypted-keys")]
+mod legacy_key;
+pub mod multi_cert;
+pub mod observer;
+pub mod peek_io;
+mod pinned;
+pub mod reconnect;
+#[cfg(feature = "tower")]
+pub mod service;
+#[cfg(feature = "test-util")]
+pub mod testing;
+pub mod timeout_stream;
 pub mod tls_setup;
 pub mod tls_streams;
 
-static CRYPTO_SETUP: AtomicBool = AtomicBool::new(false);
+static INSTALLED_PROVIDER: Mutex<Option<&'static str>> = Mutex::new(None);
+
+#[cfg(not(any(feature = "aws-lc-rs", feature = "ring")))]
+compile_error!("enable either the `aws-lc-rs` or the `ring` feature");
+
+/// Installs [`tls_setup::default_provider`]. Calling it again is a no-op, but it fails if a
+/// different provider was installed first.
+pub fn install_crypto() -> Result<(), std::io::Error> {
+    install(Some(tls_setup::DEFAULT_PROVIDER_NAME), tls_setup::default_provider)
+}
+
+/// Installs `provider`. Fails if any provider is already installed, use
+/// [`install_crypto_named`] to allow repeated installs of the same provider.
+/// With the `fips` feature only FIPS approved providers are accepted.
+pub fn install_crypto_with(provider: CryptoProvider) -> Result<(), std::io::Error> {
+    install(None, move || provider)
+}
+
+/// Installs `provider` under `name`. Installing again under the same name is a no-op, a
+/// different name fails with an error naming both providers.
+pub fn install_crypto_named(
+    name: &'static str,
+    provider: CryptoProvider,
+) -> Result<(), std::io::Error> {
+    install(Some(name), move || provider)
+}
+
+/// Name of the installed crypto provider: the name given to [`install_crypto_named`],
+/// the default provider's name for [`install_crypto`], `custom` for
+/// [`install_crypto_with`] and `unknown` when it was installed directly through rustls.
+pub fn installed_provider_name() -> Option<&'static str> {
+    let installed = *INSTALLED_PROVIDER.lock().unwrap_or_else(PoisonError::into_inner);
+    installed.or_else(|| CryptoProvider::get_default().map(|_| "unknown"))
+}
+
+fn install(
+    name: Option<&'static str>,
+    provider: impl FnOnce() -> CryptoProvider,
+) -> Result<(), std::io::Error> {
+    let requested = name.unwrap_or("custom");
+    let mut installed = INSTALLED_PROVIDER.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if let Some(existing) = *installed {
+        if name == Some(existing) {
+            tracing::debug!(provider = existing, "Crypto already setup");
+            return Ok(());
+        }
 
-pub fn install_crypto() {
-    if CRYPTO_SETUP.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
-        tracing::info!("Crypto already setup");
-        return;
+        tracing::warn!(existing, requested, "a different crypto provider is already installed");
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!(
+                "crypto provider {} is already installed, cannot install {}",
+                existing, requested
+            ),
+        ));
     }
 
-    tracing::info!("Install Crypto");
-    if rustls::crypto::aws_lc_rs::default_provider().install_default().is_err() {
-        tracing::error!("failed to install crypto");
+    let provider = provider();
+    if cfg!(feature = "fips") && !provider.fips() {
+        tracing::error!("refusing to install crypto provider that is not FIPS approved");
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "crypto provider is not FIPS approved",
+        ));
     }
+
+    tracing::debug!(provider = requested, "Install Crypto");
+    if let Err(existing) = provider.install_default() {
+        tracing::warn!(?existing, requested, "crypto provider installed outside tls_friend");
+
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!(
+                "an unknown crypto provider is already installed, cannot install {}",
+                requested
+            ),
+        ));
+    }
+
+    *installed = Some(requested);
+    Ok(())
+}
+
+/// Whether the installed crypto provider, and so every config built from it, is FIPS
+/// approved. Only true with the `fips` feature.
+pub fn is_fips() -> bool {
+    CryptoProvider::get_default().is_some_and(|provider| provider.fips())
 }
 
 pub type ClientTlsStream<IO> = tokio_rustls::client::TlsStream<IO>;
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use tokio_rustls::rustls::server::{ClientHello, ProducesTickets, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{ServerConfig, SupportedCipherSuite, SupportedProtocolVersion};
+use tokio_rustls::TlsAcceptor;
+
+use crate::error::TlsError;
+use crate::tls_setup::{
+    check_key_matches, check_key_supported, parse_key, parse_server_certificates, ConfigOptions,
+    Ticketing,
+};
+
+#[derive(Clone, Default)]
+pub struct MultiCertServer {
+    certs: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+    options: ConfigOptions,
+}
+
+impl Debug for MultiCertServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiCertServer")
+            .field("names", &self.certs.keys().collect::<Vec<_>>())
+            .field("has_default", &self.default.is_some())
+            .finish()
+    }
+}
+
+impl MultiCertServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cert(
+        mut self,
+        name: &str,
+        key_data: &[u8],
+        cert_data: &[u8],
+    ) -> Result<Self, TlsError> {
+        let key = load_certified_key(key_data, cert_data)?;
+        self.certs.insert(name.to_ascii_lowercase(), key);
+        Ok(self)
+    }
+
+    pub fn with_default_cert(
+        mut self,
+        key_data: &[u8],
+        cert_data: &[u8],
+    ) -> Result<Self, TlsError> {
+        self.default = Some(load_certified_key(key_data, cert_data)?);
+        Ok(self)
+    }
+
+    /// Same preset as [`crate::tls_setup::MutualTls::hardened`].
+    pub fn hardened(mut self) -> Self {
+        self.options.harden();
+        self
+    }
+
+    pub fn active_cipher_suites(&self) -> Result<Vec<SupportedCipherSuite>, TlsError> {
+        self.options.active_cipher_suites()
+    }
+
+    pub fn active_protocol_versions(
+        &self,
+    ) -> Result<Vec<&'static SupportedProtocolVersion>, TlsError> {
+        self.options.active_protocol_versions()
+    }
+
+    pub fn with_alpn(mut self, protocols: Vec<Vec<u8>>) -> Self {
+        self.options.alpn_protocols = protocols;
+        self
+    }
+
+    pub fn with_cipher_suites(mut self, suites: Vec<SupportedCipherSuite>) -> Self {
+        self.options.cipher_suites = Some(suites);
+        self
+    }
+
+    pub fn with_protocol_versions(
+        mut self,
+        versions: &[&'static SupportedProtocolVersion],
+    ) -> Self {
+        self.options.protocol_versions = Some(versions.to_vec());
+        self
+    }
+
+    pub fn with_ticketer(mut self) -> Self {
+        self.options.ticketer = Some(Ticketing::Default);
+        self
+    }
+
+    pub fn with_custom_ticketer(mut self, ticketer: Arc<dyn ProducesTickets>) -> Self {
+        self.options.ticketer = Some(Ticketing::Custom(ticketer));
+        self
+    }
+
+    pub fn with_max_fragment_size(mut self, size: Option<usize>) -> Self {
+        self.options.max_fragment_size = size;
+        self
+    }
+
+    /// Log TLS session secrets to the file named by `SSLKEYLOGFILE` (for Wireshark).
+    ///
+    /// This leaks the keys for every session and must never be enabled in production.
+    #[cfg(feature = "key-log")]
+    pub fn with_key_log(mut self) -> Self {
+        self.options.key_log = true;
+        self
+    }
+
+    pub fn into_acceptor(self) -> Result<TlsAcceptor, TlsError> {
+        Ok(TlsAcceptor::from(Arc::new(self.into_server_config()?)))
+    }
+
+    pub fn into_server_config(mut self) -> Result<ServerConfig, TlsError> {
+        let options = std::mem::take(&mut self.options);
+
+        let mut config = options
+            .server_builder()?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(self));
+
+        options.apply_server(&mut config)?;
+        Ok(config)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<CertifiedKey>> {
+        let name = name.to_ascii_lowercase();
+        if let Some(key) = self.certs.get(&name) {
+            return Some(key.clone());
+        }
+
+        let (_, parent) = name.split_once('.')?;
+        self.certs.get(&format!("*.{}", parent)).cloned()
+    }
+}
+
+impl ResolvesServerCert for MultiCertServer {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let found = client_hello
+            .server_name()
+            .and_then(|name| self.lookup(name));
+        if found.is_none() {
+            tracing::debug!(
+                server_name = ?client_hello.server_name(),
+                "no certificate for SNI, using default"
+            );
+            return self.default.clone();
+        }
+        found
+    }
+}
+
+fn load_certified_key(
+    key_data: &[u8],
+    cert_data: &[u8],
+) -> Result<Arc<CertifiedKey>, TlsError> {
+    let cert_chain = parse_server_certificates(key_data, cert_data)?;
+    let private_key = parse_key(key_data)?;
+    let signing_key = check_key_supported(&private_key)?;
+    check_key_matches(&cert_chain, signing_key.clone())?;
+
+    Ok(Arc::new(CertifiedKey::new(cert_chain, signing_key)))
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::server::Acceptor;
+use tokio_rustls::rustls::ProtocolVersion;
+use tokio_rustls::{LazyConfigAcceptor, TlsAcceptor, TlsConnector};
+
+use crate::async_io::AsyncIO;
+use crate::{ClientTlsStream, ServerTlsStream};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandshakeDirection {
+    Client,
+    Server,
+}
+
+#[derive(Clone, Debug)]
+pub struct HandshakeEvent<'a> {
+    pub direction: HandshakeDirection,
+    pub peer_name: Option<&'a str>,
+    pub protocol_version: Option<ProtocolVersion>,
+    /// ALPN protocols offered in the ClientHello, only filled in on the server side. Also
+    /// set when the handshake fails, e.g. because none of them are supported.
+    pub offered_alpn: &'a [Vec<u8>],
+    pub duration: Duration,
+}
+
+/// Hooks called after every TLS handshake run by a connector or acceptor, for metrics.
+pub trait HandshakeObserver: Send + Sync {
+    fn on_complete(&self, event: &HandshakeEvent<'_>);
+
+    fn on_error(&self, event: &HandshakeEvent<'_>, error: &std::io::Error);
+}
+
+pub(crate) async fn observe_connect<IO: AsyncIO>(
+    observer: Option<&Arc<dyn HandshakeObserver>>,
+    connector: &TlsConnector,
+    name: ServerName<'static>,
+    io: IO,
+) -> std::io::Result<ClientTlsStream<IO>> {
+    let Some(observer) = observer else {
+        return connector.connect(name, io).await;
+    };
+
+    let start = Instant::now();
+    let peer_name = name.to_str().into_owned();
+    let result = connector.connect(name, io).await;
+
+    let mut event = HandshakeEvent {
+        direction: HandshakeDirection::Client,
+        peer_name: Some(&peer_name),
+        protocol_version: None,
+        offered_alpn: &[],
+        duration: start.elapsed(),
+    };
+
+    match &result {
+        Ok(stream) => {
+            event.protocol_version = stream.get_ref().1.protocol_version();
+            observer.on_complete(&event);
+        }
+        Err(error) => observer.on_error(&event, error),
+    }
+
+    result
+}
+
+pub(crate) async fn observe_accept<IO: AsyncIO>(
+    observer: Option<&Arc<dyn HandshakeObserver>>,
+    acceptor: &TlsAcceptor,
+    io: IO,
+) -> std::io::Result<ServerTlsStream<IO>> {
+    let Some(observer) = observer else {
+        return acceptor.accept(io).await;
+    };
+
+    let start = Instant::now();
+    let mut offered_alpn = Vec::new();
+    let mut server_name = None;
+
+    // Read the ClientHello first so the offer can be reported even if the handshake fails,
+    // rustls does not keep it around afterwards.
+    let result = async {
+        let handshake = LazyConfigAcceptor::new(Acceptor::default(), io).await?;
+        let hello = handshake.client_hello();
+        if let Some(protocols) = hello.alpn() {
+            offered_alpn = protocols.map(<[u8]>::to_vec).collect();
+        }
+        server_name = hello.server_name().map(str::to_string);
+
+        handshake.into_stream(acceptor.config().clone()).await
+    }
+    .await;
+
+    let event = HandshakeEvent {
+        direction: HandshakeDirection::Server,
+        peer_name: server_name.as_deref(),
+        protocol_version: None,
+        offered_alpn: &offered_alpn,
+        duration: start.elapsed(),
+    };
+
+    match &result {
+        Ok(stream) => {
+            let event = HandshakeEvent {
+                protocol_version: stream.get_ref().1.protocol_version(),
+                ..event
+            };
+            observer.on_complete(&event);
+        }
+        Err(error) => observer.on_error(&event, error),
+    }
+
+    result
+}
@@ -0,0 +1,117 @@
+use std::io::Error;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+use crate::async_io::AsyncIO;
+
+/// Peek buffer used by [`PeekedIO::new`], enough for most ClientHellos in a single read.
+pub const DEFAULT_PEEK_BUFFER: usize = 512;
+
+/// Wraps an IO so the first bytes can be inspected before handing the stream on.
+/// Peeked bytes are replayed to the reader before anything else is read from `IO`.
+pub struct PeekedIO<IO: AsyncIO> {
+    buffer: Vec<u8>,
+    offset: usize,
+    capacity: usize,
+    io: IO,
+}
+
+impl<IO: AsyncIO> PeekedIO<IO> {
+    pub fn new(io: IO) -> Self {
+        Self::with_capacity(io, DEFAULT_PEEK_BUFFER)
+    }
+
+    /// A peek reads up to `capacity` bytes at once (at least one). The buffer is held per
+    /// connection until the peeked bytes have been replayed, so larger values cost memory
+    /// while smaller ones split the first read into more partial reads.
+    pub fn with_capacity(io: IO, capacity: usize) -> Self {
+        PeekedIO {
+            buffer: Vec::new(),
+            offset: 0,
+            capacity: capacity.max(1),
+            io,
+        }
+    }
+
+    /// Returns the first byte of the stream without consuming it, `None` on EOF.
+    pub async fn peek_byte(&mut self) -> Result<Option<u8>, Error> {
+        if self.offset < self.buffer.len() {
+            return Ok(Some(self.buffer[self.offset]));
+        }
+
+        let mut buffer = vec![0u8; self.capacity];
+        let read = self.io.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        buffer.truncate(read);
+        self.buffer = buffer;
+        self.offset = 0;
+        Ok(Some(self.buffer[0]))
+    }
+
+    pub fn get_ref(&self) -> &IO {
+        &self.io
+    }
+
+    pub fn into_inner(self) -> (Vec<u8>, IO) {
+        (self.buffer[self.offset..].to_vec(), self.io)
+    }
+}
+
+impl<IO: AsyncIO> AsyncRead for PeekedIO<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.offset < this.buffer.len() {
+            let remaining = &this.buffer[this.offset..];
+            let len = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..len]);
+            this.offset += len;
+            if this.offset == this.buffer.len() {
+                this.buffer = Vec::new();
+                this.offset = 0;
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        Pin::new(&mut this.io).poll_read(cx, buf)
+    }
+}
+
+impl<IO: AsyncIO> AsyncWrite for PeekedIO<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<Result<usize, Error>> {
+        Pin::new(&mut self.get_mut().io).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::client::verify_server_name;
+use tokio_rustls::rustls::crypto::{
+    verify_tls12_signature, verify_tls13_signature, CryptoProvider,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::server::ParsedCertificate;
+use tokio_rustls::rustls::{
+    CertificateError, DigitallySignedStruct, Error, SignatureScheme,
+};
+use x509_parser::time::ASN1Time;
+
+#[derive(Debug)]
+pub(crate) struct PinnedSpkiVerification {
+    hashes: Vec<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedSpkiVerification {
+    pub(crate) fn new(hashes: Vec<[u8; 32]>, provider: Arc<CryptoProvider>) -> Self {
+        PinnedSpkiVerification { hashes, provider }
+    }
+}
+
+impl ServerCertVerifier for PinnedSpkiVerification {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let parsed = ParsedCertificate::try_from(end_entity)?;
+        verify_server_name(&parsed, server_name)?;
+
+        let (_, cert) = x509_parser::parse_x509_certificate(end_entity)
+            .map_err(|_| Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        let now = ASN1Time::from_timestamp(now.as_secs() as i64)
+            .map_err(|_| Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        if now < cert.validity().not_before {
+            return Err(Error::InvalidCertificate(CertificateError::NotValidYet));
+        }
+        if cert.validity().not_after < now {
+            return Err(Error::InvalidCertificate(CertificateError::Expired));
+        }
+
+        let hash: [u8; 32] = Sha256::digest(parsed.subject_public_key_info()).into();
+        if !self.hashes.contains(&hash) {
+            tracing::error!(?server_name, "server public key does not match any pinned hash");
+            return Err(Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ));
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
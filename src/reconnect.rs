@@ -0,0 +1,255 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::io::{Error, IoSlice};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::connection_builder::{ConnectionBuilder, RetryPolicy};
+
+type ReconnectFuture<IO> = Pin<Box<dyn Future<Output = (Option<IO>, ReconnectError)> + Send>>;
+
+/// Carried inside the `io::Error` returned by a [`ReconnectingStream`] operation that hit a
+/// dropped connection, get it with [`ReconnectError::from_io_error`].
+#[derive(Debug)]
+pub enum ReconnectError {
+    /// A new connection is up. Data in flight when the old one dropped may be lost, so the
+    /// caller should resync its protocol state before continuing.
+    Reconnected { attempts: usize, cause: Error },
+    /// No connection could be established within the retry policy. The next operation
+    /// starts reconnecting again.
+    Failed { attempts: usize, last: Error },
+}
+
+impl ReconnectError {
+    pub fn from_io_error(error: &Error) -> Option<&ReconnectError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl Display for ReconnectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reconnected { attempts, cause } => write!(
+                f,
+                "connection dropped ({}), reconnected after {} attempt(s)",
+                cause, attempts
+            ),
+            Self::Failed { attempts, last } => {
+                write!(f, "reconnect failed after {} attempt(s): {}", attempts, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReconnectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Reconnected { cause, .. } => Some(cause),
+            Self::Failed { last, .. } => Some(last),
+        }
+    }
+}
+
+impl From<ReconnectError> for Error {
+    fn from(value: ReconnectError) -> Self {
+        let kind = match &value {
+            ReconnectError::Reconnected { .. } => std::io::ErrorKind::ConnectionReset,
+            ReconnectError::Failed { last, .. } => last.kind(),
+        };
+        Error::new(kind, value)
+    }
+}
+
+enum State<IO> {
+    Connected(IO),
+    Connecting(ReconnectFuture<IO>),
+    Disconnected,
+}
+
+/// A stream to `addr` that re-dials through its [`ConnectionBuilder`] when a read or write
+/// fails or the peer closes the connection. The operation that noticed the drop returns
+/// [`ReconnectError::Reconnected`] once the new connection is up, later operations use the
+/// new connection.
+pub struct ReconnectingStream<B: ConnectionBuilder> {
+    builder: Arc<B>,
+    addr: SocketAddr,
+    policy: RetryPolicy,
+    state: State<B::IO>,
+    reconnects: usize,
+}
+
+impl<B: ConnectionBuilder> ReconnectingStream<B> {
+    /// Connect to `addr`, retrying with `policy`. The same policy is used for reconnects.
+    pub async fn connect(
+        builder: B,
+        addr: SocketAddr,
+        policy: RetryPolicy,
+    ) -> std::io::Result<Self> {
+        let builder = Arc::new(builder);
+        let cause = Error::new(std::io::ErrorKind::NotConnected, "not connected");
+        let io = match reconnect(builder.clone(), addr, policy.clone(), cause).await {
+            (Some(io), _) => io,
+            (None, ReconnectError::Failed { last, .. }) => return Err(last),
+            (None, error) => return Err(error.into()),
+        };
+
+        Ok(ReconnectingStream {
+            builder,
+            addr,
+            policy,
+            state: State::Connected(io),
+            reconnects: 0,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Number of successful reconnects so far.
+    pub fn reconnects(&self) -> usize {
+        self.reconnects
+    }
+
+    /// The current connection, `None` while reconnecting.
+    pub fn get_ref(&self) -> Option<&B::IO> {
+        match &self.state {
+            State::Connected(io) => Some(io),
+            _ => None,
+        }
+    }
+
+    fn start_reconnect(&mut self, cause: Error) {
+        tracing::warn!(error = ?cause, addr = %self.addr, "connection dropped, reconnecting");
+        let future = reconnect(self.builder.clone(), self.addr, self.policy.clone(), cause);
+        self.state = State::Connecting(Box::pin(future));
+    }
+
+    /// Drives `op`, reconnecting when it fails. Returns a [`ReconnectError`] once the
+    /// reconnect settles.
+    fn poll_op<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut(Pin<&mut B::IO>, &mut Context<'_>) -> Poll<std::io::Result<T>>,
+    ) -> Poll<std::io::Result<T>> {
+        loop {
+            match &mut self.state {
+                State::Connected(io) => match op(Pin::new(io), cx) {
+                    Poll::Ready(Err(error)) => self.start_reconnect(error),
+                    other => return other,
+                },
+                State::Connecting(future) => {
+                    let (io, error) = std::task::ready!(future.as_mut().poll(cx));
+                    match io {
+                        Some(io) => {
+                            self.state = State::Connected(io);
+                            self.reconnects += 1;
+                        }
+                        None => self.state = State::Disconnected,
+                    }
+                    return Poll::Ready(Err(error.into()));
+                }
+                State::Disconnected => self.start_reconnect(Error::new(
+                    std::io::ErrorKind::NotConnected,
+                    "previous reconnect failed",
+                )),
+            }
+        }
+    }
+}
+
+async fn reconnect<B: ConnectionBuilder>(
+    builder: Arc<B>,
+    addr: SocketAddr,
+    policy: RetryPolicy,
+    cause: Error,
+) -> (Option<B::IO>, ReconnectError) {
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let error = match builder.connect(addr).await {
+            Ok(io) => {
+                let reconnected = ReconnectError::Reconnected {
+                    attempts: attempt,
+                    cause,
+                };
+                return (Some(io), reconnected);
+            }
+            Err(error) => error,
+        };
+
+        if attempt >= policy.max_attempts {
+            tracing::warn!(?cause, %addr, attempt, "giving up reconnecting");
+            let failed = ReconnectError::Failed {
+                attempts: attempt,
+                last: error,
+            };
+            return (None, failed);
+        }
+
+        let delay = policy.delay(attempt);
+        tracing::warn!(?error, %addr, attempt, ?delay, "reconnect failed, retrying");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+impl<B: ConnectionBuilder> AsyncRead for ReconnectingStream<B> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.get_mut().poll_op(cx, |io, cx| {
+            let before = buf.filled().len();
+            let result = std::task::ready!(io.poll_read(cx, buf));
+            if result.is_ok() && buf.remaining() > 0 && buf.filled().len() == before {
+                let eof = Error::new(std::io::ErrorKind::UnexpectedEof, "peer closed connection");
+                return Poll::Ready(Err(eof));
+            }
+            Poll::Ready(result)
+        })
+    }
+}
+
+impl<B: ConnectionBuilder> AsyncWrite for ReconnectingStream<B> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        self.get_mut().poll_op(cx, |io, cx| io.poll_write(cx, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.get_mut().poll_op(cx, |io, cx| io.poll_flush(cx))
+    }
+
+    /// Shuts down the current connection without reconnecting.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        match &mut self.get_mut().state {
+            State::Connected(io) => Pin::new(io).poll_shutdown(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, Error>> {
+        self.get_mut().poll_op(cx, |io, cx| io.poll_write_vectored(cx, bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match &self.state {
+            State::Connected(io) => io.is_write_vectored(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::net::TcpStream;
+use tower_service::Service;
+
+use crate::client_connector::ClientConnector;
+use crate::connection_builder::ConnectionBuilder;
+use crate::tls_streams::ClientStream;
+
+type ConnectFuture<T> = Pin<Box<dyn Future<Output = std::io::Result<T>> + Send>>;
+
+impl Service<SocketAddr> for ClientConnector {
+    type Response = ClientStream<TcpStream>;
+    type Error = std::io::Error;
+    type Future = ConnectFuture<Self::Response>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, addr: SocketAddr) -> Self::Future {
+        let connector = self.clone();
+        Box::pin(async move { ConnectionBuilder::connect(&connector, addr).await })
+    }
+}
+
+#[cfg(feature = "hyper")]
+pub use self::hyper_io::{HyperServerStream, HyperStream};
+
+#[cfg(feature = "hyper")]
+mod hyper_io {
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use hyper::rt::{Read, ReadBufCursor, Write};
+    use hyper::Uri;
+    use hyper_util::client::legacy::connect::{Connected, Connection};
+    use hyper_util::rt::TokioIo;
+    use tokio::net::TcpStream;
+    use tower_service::Service;
+
+    use super::ConnectFuture;
+    use crate::client_connector::ClientConnector;
+    use crate::tls_streams::{ClientStream, ServerStream, TlsInfo};
+
+    /// A [`ClientStream`] usable as a hyper connection, returned when a [`ClientConnector`]
+    /// is used as a hyper connector.
+    pub struct HyperStream(TokioIo<ClientStream<TcpStream>>);
+
+    impl HyperStream {
+        pub fn get_ref(&self) -> &ClientStream<TcpStream> {
+            self.0.inner()
+        }
+
+        pub fn into_inner(self) -> ClientStream<TcpStream> {
+            self.0.into_inner()
+        }
+    }
+
+    /// Lets a `hyper_util` client dial through this connector. The URI host is used for SNI
+    /// and certificate verification instead of the name the connector was built with, and
+    /// `http` URIs get a plaintext stream.
+    impl Service<Uri> for ClientConnector {
+        type Response = HyperStream;
+        type Error = std::io::Error;
+        type Future = ConnectFuture<Self::Response>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, uri: Uri) -> Self::Future {
+            let connector = self.clone();
+            Box::pin(async move {
+                let Some(host) = uri.host() else {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "uri has no host",
+                    ));
+                };
+                let plaintext = uri.scheme_str() == Some("http");
+                let port = match (uri.port_u16(), plaintext) {
+                    (Some(port), _) => port,
+                    (None, true) => 80,
+                    (None, false) => 443,
+                };
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+
+                let stream = TcpStream::connect((host, port)).await?;
+                if plaintext {
+                    return Ok(HyperStream(TokioIo::new(ClientStream::TcpStream(stream))));
+                }
+                let stream = connector.connect_with_name(host, stream).await?;
+                Ok(HyperStream(TokioIo::new(stream)))
+            })
+        }
+    }
+
+    impl Connection for HyperStream {
+        fn connected(&self) -> Connected {
+            let connected = Connected::new();
+            match self.get_ref().alpn_protocol() {
+                Some(b"h2") => connected.negotiated_h2(),
+                _ => connected,
+            }
+        }
+    }
+
+    impl Read for HyperStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: ReadBufCursor<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+        }
+    }
+
+    impl Write for HyperStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            self.0.is_write_vectored()
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[std::io::IoSlice<'_>],
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut self.get_mut().0).poll_write_vectored(cx, bufs)
+        }
+    }
+
+    /// An accepted [`ServerStream`] usable as a hyper server connection, e.g. with
+    /// `hyper::server::conn::http1::Builder::serve_connection`.
+    pub struct HyperServerStream(TokioIo<ServerStream<TcpStream>>);
+
+    impl HyperServerStream {
+        pub fn new(stream: ServerStream<TcpStream>) -> Self {
+            HyperServerStream(TokioIo::new(stream))
+        }
+
+        pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+            self.get_ref().peer_addr()
+        }
+
+        /// `None` for plaintext connections.
+        pub fn tls_info(&self) -> Option<TlsInfo> {
+            self.get_ref().tls_info()
+        }
+
+        pub fn get_ref(&self) -> &ServerStream<TcpStream> {
+            self.0.inner()
+        }
+
+        pub fn into_inner(self) -> ServerStream<TcpStream> {
+            self.0.into_inner()
+        }
+    }
+
+    impl From<ServerStream<TcpStream>> for HyperServerStream {
+        fn from(stream: ServerStream<TcpStream>) -> Self {
+            Self::new(stream)
+        }
+    }
+
+    impl Read for HyperServerStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: ReadBufCursor<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+        }
+    }
+
+    impl Write for HyperServerStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            self.0.is_write_vectored()
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[std::io::IoSlice<'_>],
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut self.get_mut().0).poll_write_vectored(cx, bufs)
+        }
+    }
+}
@@ -1,9 +1,41 @@
-use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::{client_connector::ClientConnector, tls_setup::TlsSetup};
+use sha2::{Digest, Sha256};
+use tokio::io::{duplex, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{
+    self,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    client::WebPkiServerVerifier,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    CipherSuite, DigitallySignedStruct, HandshakeKind, ProtocolVersion, RootCertStore,
+    SignatureScheme,
+};
+use tokio_rustls::TlsConnector;
+
+use crate::{
+    async_io::AsyncIO,
+    client_connector::{ClientAcceptor, ClientConnector, ReloadableAcceptor},
+    config_builder::TlsConfigBuilder,
+    connection_builder::{
+        AcceptBuilder, ConnectionBuilder, HostConnectionBuilder, PlaintextFallback,
+        ProxyConnectionBuilder, RetryPolicy, SocketOptions, Socks5ConnectionBuilder,
+    },
+    error::TlsError,
+    observer::{HandshakeDirection, HandshakeEvent, HandshakeObserver},
+    reconnect::{ReconnectError, ReconnectingStream},
+    tls_setup::{
+        default_provider, parse_crls, ClientTls, ClientVerifyServerTls, MutualTls, OpenServerTls,
+        ServerTls, TlsSetup,
+    },
+    tls_streams::{ClientStream, MaybeTlsStream, ServerStream},
+};
 
 #[tokio::test]
 async fn simple_tls_connection_test() {
+    crate::install_crypto().unwrap();
     let server = TlsSetup::build_mutual(
         include_bytes!("./res/client_ca.crt"),
         include_bytes!("./res/server.key"),
@@ -53,3 +85,3507 @@ async fn simple_tls_connection_test() {
     assert_eq!("I'm done".as_bytes(), &recv_buffer);
 }
 
+#[tokio::test]
+async fn peer_certificates_test() {
+    crate::install_crypto().unwrap();
+    let mutual_acceptor = ClientAcceptor::from_server_tls(
+        TlsSetup::build_mutual(
+            include_bytes!("./res/client_ca.crt"),
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let mutual_connector = ClientConnector::from_client_tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_mutual(
+            include_bytes!("./res/server_ca.crt"),
+            include_bytes!("./res/client.key"),
+            include_bytes!("./res/client.crt"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let (server_io, client_io) =
+        tokio::join!(mutual_acceptor.accept(server_io), mutual_connector.connect(client_io));
+    let (server_io, client_io) = (server_io.unwrap(), client_io.unwrap());
+    let server_chain =
+        crate::tls_setup::parse_certificates(include_bytes!("./res/server.crt")).unwrap();
+    assert_eq!(client_io.peer_certificates(), Some(&server_chain[..]));
+    assert!(server_io.peer_certificates().is_some_and(|certs| !certs.is_empty()));
+
+    // a server without client auth receives no client certificate
+    let acceptor = ClientAcceptor::from_server_tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let (server_io, client_io) = duplex(4096);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), mutual_connector.connect(client_io));
+    assert!(client_io.unwrap().peer_certificates().is_some());
+    assert!(server_io.unwrap().peer_certificates().is_none());
+
+    let (server_io, client_io) = duplex(4096);
+    let client_io = ClientConnector::plain().connect(client_io).await.unwrap();
+    let server_io = ClientAcceptor::plain().accept(server_io).await.unwrap();
+    assert!(client_io.peer_certificates().is_none());
+    assert!(server_io.peer_certificates().is_none());
+}
+
+#[tokio::test]
+async fn negotiated_parameters_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::from_server_tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let connector = ClientConnector::from_client_tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .with_protocol_versions(&[&rustls::version::TLS12]),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+    let (server_io, client_io) = (server_io.unwrap(), client_io.unwrap());
+    assert_eq!(client_io.protocol_version(), Some(ProtocolVersion::TLSv1_2));
+    assert_eq!(server_io.protocol_version(), Some(ProtocolVersion::TLSv1_2));
+    let suite = client_io.negotiated_cipher_suite().unwrap();
+    assert_eq!(suite.version(), &rustls::version::TLS12);
+    assert_eq!(server_io.negotiated_cipher_suite().unwrap().suite(), suite.suite());
+
+    let (server_io, client_io) = duplex(4096);
+    let client_io = ClientConnector::plain().connect(client_io).await.unwrap();
+    let server_io = ClientAcceptor::plain().accept(server_io).await.unwrap();
+    assert!(client_io.protocol_version().is_none());
+    assert!(client_io.negotiated_cipher_suite().is_none());
+    assert!(server_io.protocol_version().is_none());
+    assert!(server_io.negotiated_cipher_suite().is_none());
+}
+
+#[tokio::test]
+async fn client_stream_split_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+    let client_io = connector.connect(client_io).await.unwrap();
+    let mut server_io = server_accept_task.await.unwrap().unwrap();
+
+    let (mut client_read, mut client_write) = client_io.into_split();
+    client_write.write_all(b"hello world").await.unwrap();
+    let mut recv_buffer = [0u8; 11];
+    server_io.read_exact(&mut recv_buffer).await.unwrap();
+    assert_eq!(&recv_buffer, b"hello world");
+
+    server_io.write_all("I'm done".as_bytes()).await.unwrap();
+    server_io.shutdown().await.unwrap();
+
+    let mut recv_buffer = Vec::new();
+    client_read.read_to_end(&mut recv_buffer).await.unwrap();
+    assert_eq!("I'm done".as_bytes(), &recv_buffer);
+
+    let _client_io: ClientStream<DuplexStream> =
+        AsyncIO::try_join(client_read, client_write).ok().unwrap();
+}
+
+#[tokio::test]
+async fn alpn_negotiation_test() {
+    crate::install_crypto().unwrap();
+    let server = TlsSetup::build_mutual(
+        include_bytes!("./res/client_ca.crt"),
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .with_alpn(vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+    let client = TlsSetup::build_mutual(
+        include_bytes!("./res/server_ca.crt"),
+        include_bytes!("./res/client.key"),
+        include_bytes!("./res/client.crt"),
+    )
+    .unwrap()
+    .with_alpn(vec![b"h2".to_vec()]);
+
+    let acceptor = server.into_acceptor().unwrap();
+    let connector = client.into_connector().unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+
+    let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+    let client_connector =
+        ClientConnector::tls("s1.testing-server.playit.cloud", connector).unwrap();
+
+    let client_io = client_connector.connect(client_io).await.unwrap();
+    let server_io = server_accept_task.await.unwrap().unwrap();
+
+    assert_eq!(client_io.alpn_protocol(), Some(&b"h2"[..]));
+    assert_eq!(server_io.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+    let (_, plain_io) = duplex(1024);
+    let plain_io = ClientConnector::plain().connect(plain_io).await.unwrap();
+    assert_eq!(plain_io.alpn_protocol(), None);
+}
+
+#[tokio::test]
+async fn config_builder_test() {
+    crate::install_crypto().unwrap();
+    let server = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .with_alpn(vec![b"http/1.1".to_vec()]);
+    let acceptor = TlsConfigBuilder::new(server)
+        .alpn(vec![b"h2".to_vec(), b"http/1.1".to_vec()])
+        .build_acceptor()
+        .unwrap();
+
+    let client = TlsSetup::build_client(include_bytes!("./res/server_ca.crt")).unwrap();
+    let client_config = TlsConfigBuilder::new(client)
+        .alpn(vec![b"h2".to_vec()])
+        .protocol_versions(&[&rustls::version::TLS12])
+        .session_cache(16)
+        .build_client_config()
+        .unwrap();
+    assert_eq!(client_config.alpn_protocols, vec![b"h2".to_vec()]);
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsConnector::from(Arc::new(client_config)),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+    let (server_io, client_io) = (server_io.unwrap(), client_io.unwrap());
+    assert_eq!(client_io.alpn_protocol(), Some(&b"h2"[..]));
+    assert_eq!(client_io.protocol_version(), Some(ProtocolVersion::TLSv1_2));
+    assert_eq!(server_io.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+}
+
+#[test]
+fn encrypted_key_test() {
+    crate::install_crypto().unwrap();
+    let tls = TlsSetup::build_mutual_with_password(
+        include_bytes!("./res/client_ca.crt"),
+        include_bytes!("./res/server_encrypted.key"),
+        include_bytes!("./res/server.crt"),
+        "hunter2",
+    )
+    .unwrap();
+    tls.into_acceptor().unwrap();
+
+    let error = TlsSetup::build_mutual_with_password(
+        include_bytes!("./res/client_ca.crt"),
+        include_bytes!("./res/server_encrypted.key"),
+        include_bytes!("./res/server.crt"),
+        "wrong",
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(error, TlsError::WrongPassword));
+
+    TlsSetup::build_mutual_with_password(
+        include_bytes!("./res/client_ca.crt"),
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+        "hunter2",
+    )
+    .unwrap();
+}
+
+#[test]
+fn from_pem_test() {
+    crate::install_crypto().unwrap();
+    let combined = format!(
+        "{}{}",
+        include_str!("./res/server.key"),
+        include_str!("./res/server.crt")
+    );
+
+    TlsSetup::from_pem_mutual(include_str!("./res/client_ca.crt"), &combined, "").unwrap();
+    TlsSetup::from_pem_server(&combined, "").unwrap();
+    TlsSetup::from_pem_client(include_str!("./res/server_ca.crt")).unwrap();
+}
+
+#[tokio::test]
+async fn pkcs12_connection_test() {
+    crate::install_crypto().unwrap();
+    let server = TlsSetup::build_mutual(
+        include_bytes!("./res/client_ca.crt"),
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap();
+
+    let client = TlsSetup::build_pkcs12(include_bytes!("./res/client.p12"), "hunter2").unwrap();
+    assert!(TlsSetup::build_pkcs12(include_bytes!("./res/client.p12"), "wrong").is_err());
+
+    // intermediates of the leaf are no trust anchors, so without a CA bag there is no trust
+    let no_ca = include_bytes!("./res/client_no_ca.p12");
+    let error = TlsSetup::build_pkcs12(no_ca, "hunter2").err().unwrap();
+    assert!(matches!(&error, TlsError::Pkcs12(reason) if reason.contains("no CA certificate")));
+    TlsSetup::build_pkcs12_server(no_ca, "hunter2").unwrap();
+
+    let acceptor = server.into_acceptor().unwrap();
+    let connector = client.into_connector().unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+
+    let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+    let client_connector =
+        ClientConnector::tls("s1.testing-server.playit.cloud", connector).unwrap();
+
+    let _client_io = client_connector.connect(client_io).await.unwrap();
+    server_accept_task.await.unwrap().unwrap();
+}
+
+#[test]
+fn der_encoded_test() {
+    crate::install_crypto().unwrap();
+    let server = TlsSetup::build_server(
+        include_bytes!("./res/server_key.der"),
+        include_bytes!("./res/server_crt.der"),
+    )
+    .unwrap();
+    server.into_acceptor().unwrap();
+
+    TlsSetup::build_client(include_bytes!("./res/server_crt.der")).unwrap();
+}
+
+#[test]
+fn webpki_roots_test() {
+    crate::install_crypto().unwrap();
+    let client = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .with_webpki_roots();
+    client.into_connector().unwrap();
+
+    TlsSetup::client_webpki_roots().into_connector().unwrap();
+}
+
+#[tokio::test]
+async fn sni_multi_cert_test() {
+    crate::install_crypto().unwrap();
+    let mut certs = HashMap::new();
+    certs.insert(
+        "*.testing-server.playit.cloud".to_string(),
+        (
+            include_bytes!("./res/server.key").to_vec(),
+            include_bytes!("./res/server.crt").to_vec(),
+        ),
+    );
+    let acceptor = TlsSetup::server_with_sni(certs).unwrap().into_acceptor().unwrap();
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(acceptor.clone().accept(server_io));
+    let _client_io = ClientConnector::tls("s1.testing-server.playit.cloud", connector.clone())
+        .unwrap()
+        .connect(client_io)
+        .await
+        .unwrap();
+    server_accept_task.await.unwrap().unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+    let result = ClientConnector::tls("unknown.example.com", connector)
+        .unwrap()
+        .connect(client_io)
+        .await;
+    assert!(result.is_err());
+    assert!(server_accept_task.await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn cipher_suite_selection_test() {
+    crate::install_crypto().unwrap();
+    let suite = default_provider()
+        .cipher_suites
+        .into_iter()
+        .find(|suite| suite.suite() == CipherSuite::TLS13_AES_256_GCM_SHA384)
+        .unwrap();
+
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .with_cipher_suites(vec![suite])
+    .into_acceptor()
+    .unwrap();
+
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+    let _client_io = ClientConnector::tls("s1.testing-server.playit.cloud", connector)
+        .unwrap()
+        .connect(client_io)
+        .await
+        .unwrap();
+    let server_io = server_accept_task.await.unwrap().unwrap();
+    assert_eq!(server_io.get_ref().1.negotiated_cipher_suite(), Some(suite));
+
+    let error = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .with_cipher_suites(vec![])
+        .into_client_config()
+        .err()
+        .unwrap();
+    assert!(matches!(error, TlsError::NoCipherSuites));
+    assert_eq!(std::io::Error::from(error).kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn handshake_timeout_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+
+    let (server_io, _client_io) = duplex(1024);
+    let error = ClientAcceptor::tls(acceptor)
+        .accept_with_timeout(server_io, Duration::from_millis(50))
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+    let (server_io, _client_io) = duplex(1024);
+    ClientAcceptor::plain()
+        .accept_with_timeout(server_io, Duration::from_millis(50))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn optional_client_auth_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_mutual(
+        include_bytes!("./res/client_ca.crt"),
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .allow_unauthenticated_clients()
+    .into_acceptor()
+    .unwrap();
+
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(async move {
+        ClientAcceptor::tls(acceptor).accept(server_io).await
+    });
+    let _client_io = ClientConnector::tls("s1.testing-server.playit.cloud", connector)
+        .unwrap()
+        .connect(client_io)
+        .await
+        .unwrap();
+
+    let server_io = server_accept_task.await.unwrap().unwrap();
+    assert!(server_io.peer_certificates().is_none());
+    assert_eq!(server_io.server_name(), Some("s1.testing-server.playit.cloud"));
+}
+
+#[tokio::test]
+async fn reloadable_acceptor_test() {
+    crate::install_crypto().unwrap();
+    let server = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap();
+
+    let acceptor = ReloadableAcceptor::new(server.clone().into()).unwrap();
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .with_alpn(vec![b"h2".to_vec()])
+        .into_connector()
+        .unwrap();
+    let connector = ClientConnector::tls("s1.testing-server.playit.cloud", connector).unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn({
+        let acceptor = acceptor.clone();
+        async move { acceptor.accept(server_io).await }
+    });
+    let _client_io = connector.connect(client_io).await.unwrap();
+    let server_io = server_accept_task.await.unwrap().unwrap();
+    assert_eq!(server_io.alpn_protocol(), None);
+
+    acceptor.reload(server.with_alpn(vec![b"h2".to_vec()]).into()).unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(async move { acceptor.accept(server_io).await });
+    let _client_io = connector.connect(client_io).await.unwrap();
+    let server_io = server_accept_task.await.unwrap().unwrap();
+    assert_eq!(server_io.alpn_protocol(), Some(&b"h2"[..]));
+}
+
+#[cfg(feature = "dangerous-insecure")]
+#[tokio::test]
+async fn insecure_no_verify_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+
+    let connector = crate::tls_setup::ClientVerifyServerTls::insecure_no_verify()
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+    let _client_io = ClientConnector::tls("not-the-cert-name.example.com", connector)
+        .unwrap()
+        .connect(client_io)
+        .await
+        .unwrap();
+    server_accept_task.await.unwrap().unwrap();
+}
+
+#[test]
+fn malformed_pem_test() {
+    crate::install_crypto().unwrap();
+    let malformed = b"-----BEGIN CERTIFICATE-----\n!!not base64!!\n-----END CERTIFICATE-----\n";
+
+    let error = TlsSetup::build_client(malformed).err().unwrap();
+    assert_eq!(std::io::Error::from(error).kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn host_connection_builder_test() {
+    crate::install_crypto().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let accept_task = tokio::spawn(async move { listener.accept().await });
+    let stream = HostConnectionBuilder::plain()
+        .connect(&format!("localhost:{}", port))
+        .await
+        .unwrap();
+    accept_task.await.unwrap().unwrap();
+    assert!(stream.alpn_protocol().is_none());
+
+    assert!(HostConnectionBuilder::plain().connect("localhost").await.is_err());
+}
+
+#[tokio::test]
+async fn happy_eyeballs_test() {
+    crate::install_crypto().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let accept_task = tokio::spawn(async move { listener.accept().await });
+    ClientConnector::plain()
+        .connect_happy_eyeballs("localhost", port)
+        .await
+        .unwrap();
+    accept_task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn socket_options_test() {
+    crate::install_crypto().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let accept_task = tokio::spawn(async move { listener.accept().await });
+    let options = SocketOptions {
+        nodelay: true,
+        keepalive: Some(Duration::from_secs(30)),
+    };
+    let stream = ClientConnector::plain()
+        .connect_with_options(addr, &options)
+        .await
+        .unwrap();
+    accept_task.await.unwrap().unwrap();
+
+    let ClientStream::TcpStream(tcp) = stream else { panic!("expected plain stream") };
+    assert!(tcp.nodelay().unwrap());
+}
+
+#[tokio::test]
+async fn shutdown_with_timeout_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(async move {
+        ClientAcceptor::tls(acceptor).accept(server_io).await
+    });
+    let mut client_io = ClientConnector::tls("s1.testing-server.playit.cloud", connector)
+        .unwrap()
+        .connect(client_io)
+        .await
+        .unwrap();
+    let mut server_io = server_accept_task.await.unwrap().unwrap();
+
+    assert!(client_io.shutdown_with_timeout(Duration::from_secs(1)).await.unwrap());
+
+    let mut recv_buffer = Vec::new();
+    server_io.read_to_end(&mut recv_buffer).await.unwrap();
+    assert!(recv_buffer.is_empty());
+}
+
+#[tokio::test]
+async fn load_client_multi_test() {
+    crate::install_crypto().unwrap();
+    let res = concat!(env!("CARGO_MANIFEST_DIR"), "/src/res");
+    let server_ca = format!("{}/server_ca.crt", res);
+    let client_ca = format!("{}/client_ca.crt", res);
+    let not_a_ca = format!("{}/server.key", res);
+
+    TlsSetup::load_client_multi(&[&server_ca, &client_ca, &not_a_ca]).await.unwrap();
+    assert!(TlsSetup::load_client_multi(&[&not_a_ca]).await.is_err());
+
+    let mut client = TlsSetup::build_client(include_bytes!("./res/server_ca.crt")).unwrap();
+    client.add_ca_pem(include_bytes!("./res/client_ca.crt")).unwrap();
+    assert!(client.add_ca_pem(b"not a pem").is_err());
+}
+
+#[test]
+fn certificate_expiry_test() {
+    crate::install_crypto().unwrap();
+    let server = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap();
+
+    let not_after = server.leaf_not_after().unwrap();
+    let expected = std::time::UNIX_EPOCH + Duration::from_secs(1801460150);
+    assert_eq!(not_after, expected);
+
+    assert!(server.expires_within(Duration::from_secs(100 * 365 * 24 * 60 * 60)));
+}
+
+#[test]
+fn subject_alt_names_test() {
+    crate::install_crypto().unwrap();
+    let server = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap();
+    assert_eq!(server.subject_alt_names(), vec!["s1.testing-server.playit.cloud"]);
+
+    let server = TlsSetup::build_mutual(
+        include_bytes!("./res/client_ca.crt"),
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap();
+    assert_eq!(server.subject_alt_names(), vec!["s1.testing-server.playit.cloud"]);
+
+    #[cfg(feature = "test-util")]
+    {
+        let (server, client) =
+            crate::testing::generate_self_signed(&["api.internal", "127.0.0.1", "::1"]);
+        assert_eq!(server.subject_alt_names(), vec!["api.internal", "127.0.0.1", "::1"]);
+        assert!(client.subject_alt_names().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn key_algorithm_matrix_test() {
+    crate::install_crypto().unwrap();
+    let cases: [(&str, &[u8], &[u8]); 3] = [
+        (
+            "s1.testing-ed25519.playit.cloud",
+            include_bytes!("./res/ed25519_ca.crt"),
+            include_bytes!("./res/ed25519.pem"),
+        ),
+        (
+            "s1.testing-p256.playit.cloud",
+            include_bytes!("./res/p256_ca.crt"),
+            include_bytes!("./res/p256.pem"),
+        ),
+        (
+            "s1.testing-p384.playit.cloud",
+            include_bytes!("./res/p384_ca.crt"),
+            include_bytes!("./res/p384.pem"),
+        ),
+    ];
+
+    for (name, ca, identity) in cases {
+        let acceptor = TlsSetup::build_mutual(ca, identity, b"")
+            .unwrap()
+            .into_acceptor()
+            .unwrap();
+        let connector = TlsSetup::build_mutual(ca, identity, b"")
+            .unwrap()
+            .into_connector()
+            .unwrap();
+
+        let (server_io, client_io) = duplex(1024);
+        let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+        let _client_io = ClientConnector::tls(name, connector)
+            .unwrap()
+            .connect(client_io)
+            .await
+            .unwrap();
+        server_accept_task.await.unwrap().unwrap();
+    }
+
+    let error = TlsSetup::build_server(
+        include_bytes!("./res/ed448.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(error, TlsError::UnsupportedKeyAlgorithm(ref alg) if alg == "Ed448"));
+}
+
+#[derive(Debug)]
+struct RecordOcsp {
+    inner: Arc<dyn ServerCertVerifier>,
+    ocsp: Arc<Mutex<Vec<u8>>>,
+}
+
+impl ServerCertVerifier for RecordOcsp {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        *self.ocsp.lock().unwrap() = ocsp_response.to_vec();
+        self.inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[tokio::test]
+async fn ocsp_stapling_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .with_ocsp(b"stapled-ocsp-response".to_vec())
+    .into_acceptor()
+    .unwrap();
+
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut &include_bytes!("./res/server_ca.crt")[..]) {
+        roots.add(cert.unwrap()).unwrap();
+    }
+
+    let ocsp = Arc::new(Mutex::new(Vec::new()));
+    let mut config = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_client_config()
+        .unwrap();
+    config.dangerous().set_certificate_verifier(Arc::new(RecordOcsp {
+        inner: WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(default_provider()),
+        )
+        .build()
+        .unwrap(),
+        ocsp: ocsp.clone(),
+    }));
+
+    let (server_io, client_io) = duplex(1024);
+    let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+    let _client_io = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsConnector::from(Arc::new(config)),
+    )
+    .unwrap()
+    .connect(client_io)
+    .await
+    .unwrap();
+    server_accept_task.await.unwrap().unwrap();
+
+    assert_eq!(&ocsp.lock().unwrap()[..], b"stapled-ocsp-response");
+}
+
+#[tokio::test]
+async fn pinned_spki_test() {
+    crate::install_crypto().unwrap();
+    let server_pem = include_bytes!("./res/server.crt");
+    let leaf = rustls_pemfile::certs(&mut &server_pem[..]).next().unwrap().unwrap();
+    let (_, leaf) = x509_parser::parse_x509_certificate(&leaf).unwrap();
+    let pin: [u8; 32] = Sha256::digest(leaf.public_key().raw).into();
+
+    let acceptor = TlsSetup::build_server(include_bytes!("./res/server.key"), server_pem)
+        .unwrap()
+        .into_acceptor()
+        .unwrap();
+
+    for (name, pins, should_connect) in [
+        ("s1.testing-server.playit.cloud", vec![[0u8; 32], pin], true),
+        ("s1.testing-server.playit.cloud", vec![[0u8; 32]], false),
+        ("other.playit.cloud", vec![pin], false),
+    ] {
+        let connector = TlsSetup::client_with_pinned_spki(pins).unwrap().into_connector().unwrap();
+
+        let (server_io, client_io) = duplex(4096);
+        let server_accept_task = tokio::spawn(acceptor.accept(server_io));
+        let result = ClientConnector::tls(name, connector)
+            .unwrap()
+            .connect(client_io)
+            .await;
+
+        assert_eq!(result.is_ok(), should_connect);
+        assert_eq!(server_accept_task.await.unwrap().is_ok(), should_connect);
+    }
+}
+
+#[tokio::test]
+async fn accept_detect_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let server_task = {
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move { acceptor.accept_detect(server_io).await })
+    };
+    let mut client_io = connector.connect(client_io).await.unwrap();
+    let mut server_io = server_task.await.unwrap().unwrap();
+    assert!(matches!(server_io, ServerStream::TlsStream(_)));
+    assert!(server_io.is_encrypted());
+    assert!(client_io.is_encrypted());
+
+    client_io.write_all(b"hello").await.unwrap();
+    client_io.flush().await.unwrap();
+    let mut buf = [0u8; 5];
+    server_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+
+    let (server_io, mut client_io) = duplex(4096);
+    client_io.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    let server_io = acceptor.accept_or_plain(server_io).await.unwrap();
+    assert!(matches!(server_io, ServerStream::TcpStream(_)));
+    assert!(server_io.is_plaintext());
+
+    let mut server_io = MaybeTlsStream::Server(server_io);
+    assert!(!server_io.is_encrypted());
+
+    let mut buf = [0u8; 16];
+    server_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"GET / HTTP/1.1\r\n");
+}
+
+#[tokio::test]
+async fn session_resumption_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .with_session_cache(16)
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let mut kinds = Vec::new();
+    for _ in 0..2 {
+        let (server_io, client_io) = duplex(4096);
+        let server_task = {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let mut server_io = acceptor.accept(server_io).await.unwrap();
+                server_io.write_all(b"ok").await.unwrap();
+                server_io.flush().await.unwrap();
+                server_io
+            })
+        };
+
+        let mut client_io = connector.connect(client_io).await.unwrap();
+        let mut buf = [0u8; 2];
+        client_io.read_exact(&mut buf).await.unwrap();
+        let _server_io = server_task.await.unwrap();
+
+        let ClientStream::TlsStream(tls) = &client_io else {
+            panic!("expected tls stream");
+        };
+        kinds.push(tls.get_ref().1.handshake_kind());
+    }
+
+    assert_eq!(kinds, vec![Some(HandshakeKind::Full), Some(HandshakeKind::Resumed)]);
+}
+
+#[tokio::test]
+async fn session_ticket_test() {
+    crate::install_crypto().unwrap();
+    let server = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap();
+    assert!(!server.clone().into_server_config().unwrap().ticketer.enabled());
+
+    let config = server.with_ticketer().into_server_config().unwrap();
+    assert!(config.ticketer.enabled());
+
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let mut resumed = Vec::new();
+    for _ in 0..2 {
+        let (server_io, client_io) = duplex(4096);
+        let server_task = {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let mut server_io = acceptor.accept(server_io).await.unwrap();
+                server_io.write_all(b"ok").await.unwrap();
+                server_io.flush().await.unwrap();
+                server_io
+            })
+        };
+
+        let mut client_io = connector.connect(client_io).await.unwrap();
+        let mut buf = [0u8; 2];
+        client_io.read_exact(&mut buf).await.unwrap();
+        let server_io = server_task.await.unwrap();
+        resumed.push(server_io.get_ref().1.handshake_kind() == Some(HandshakeKind::Resumed));
+    }
+
+    assert_eq!(resumed, vec![false, true]);
+}
+
+#[tokio::test]
+async fn early_data_test() {
+    crate::install_crypto().unwrap();
+    let observer = Arc::new(RecordHandshakes::default());
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let client = TlsSetup::build_client(include_bytes!("./res/server_ca.crt")).unwrap();
+
+    let (_, client_io) = duplex(4096);
+    let error = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        client.clone().into_connector().unwrap(),
+    )
+    .unwrap()
+    .connect_early(client_io, b"hello")
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        client.with_early_data().into_connector().unwrap(),
+    )
+    .unwrap()
+    .with_observer(observer.clone());
+
+    for _ in 0..2 {
+        let (server_io, client_io) = duplex(4096);
+        let server_task = {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let mut server_io = acceptor.accept(server_io).await.unwrap();
+                let mut buf = [0u8; 5];
+                server_io.read_exact(&mut buf).await.unwrap();
+                server_io.write_all(b"ok").await.unwrap();
+                server_io.flush().await.unwrap();
+                buf
+            })
+        };
+
+        let mut client_io = connector.connect_early(client_io, b"hello").await.unwrap();
+        let mut buf = [0u8; 2];
+        client_io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&server_task.await.unwrap(), b"hello");
+    }
+
+    let events = observer.events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    for (direction, peer_name, _, complete) in events.iter() {
+        assert_eq!(*direction, HandshakeDirection::Client);
+        assert_eq!(peer_name.as_deref(), Some("s1.testing-server.playit.cloud"));
+        assert!(complete);
+    }
+}
+
+#[tokio::test]
+async fn stream_inner_io_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        acceptor.accept(tcp).await.unwrap()
+    });
+
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut client_io = connector.connect(tcp).await.unwrap();
+    let server_io = server_task.await.unwrap();
+
+    assert_eq!(client_io.get_ref().peer_addr().unwrap(), addr);
+    assert_eq!(server_io.get_ref().local_addr().unwrap(), addr);
+    client_io.get_mut().set_nodelay(true).unwrap();
+    assert!(client_io.get_ref().nodelay().unwrap());
+
+    let tcp = client_io.into_inner();
+    assert_eq!(tcp.peer_addr().unwrap(), addr);
+
+    let (_, plain_io) = duplex(1024);
+    let _plain: DuplexStream = ClientConnector::plain()
+        .connect(plain_io)
+        .await
+        .unwrap()
+        .into_inner();
+}
+
+async fn accept_generic<A: AcceptBuilder>(acceptor: &A, listener: &TcpListener) -> A::IO {
+    let (stream, _) = listener.accept().await.unwrap();
+    acceptor.accept(stream).await.unwrap()
+}
+
+#[tokio::test]
+async fn accept_builder_test() {
+    crate::install_crypto().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let client_task = tokio::spawn(async move {
+        let mut client_io = ConnectionBuilder::connect(&connector, addr).await.unwrap();
+        client_io.write_all(b"hello").await.unwrap();
+        client_io.flush().await.unwrap();
+        client_io
+    });
+
+    let mut server_io = accept_generic(&acceptor, &listener).await;
+    let _client_io = client_task.await.unwrap();
+
+    let mut buf = [0u8; 5];
+    server_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+#[tokio::test]
+async fn from_parts_test() {
+    crate::install_crypto().unwrap();
+    fn certs(pem: &[u8]) -> Vec<CertificateDer<'static>> {
+        rustls_pemfile::certs(&mut &pem[..]).map(|cert| cert.unwrap()).collect()
+    }
+
+    fn key(pem: &[u8]) -> PrivateKeyDer<'static> {
+        rustls_pemfile::private_key(&mut &pem[..]).unwrap().unwrap()
+    }
+
+    fn roots(pem: &[u8]) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(certs(pem));
+        roots
+    }
+
+    let server = MutualTls::from_parts(
+        roots(include_bytes!("./res/client_ca.crt")),
+        certs(include_bytes!("./res/server.crt")),
+        key(include_bytes!("./res/server.key")),
+    )
+    .unwrap();
+    let client = MutualTls::from_parts(
+        roots(include_bytes!("./res/server_ca.crt")),
+        certs(include_bytes!("./res/client.crt")),
+        key(include_bytes!("./res/client.key")),
+    )
+    .unwrap();
+
+    let acceptor = server.into_acceptor().unwrap();
+    let (server_io, client_io) = duplex(4096);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let _client_io = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        client.into_connector().unwrap(),
+    )
+    .unwrap()
+    .connect(client_io)
+    .await
+    .unwrap();
+    server_task.await.unwrap().unwrap();
+
+    let open = OpenServerTls::from_parts(
+        certs(include_bytes!("./res/server.crt")),
+        key(include_bytes!("./res/server.key")),
+    );
+    assert!(open.is_ok());
+
+    let error = OpenServerTls::from_parts(Vec::new(), key(include_bytes!("./res/server.key")));
+    assert!(matches!(error.err(), Some(TlsError::NoCertificates)));
+
+    let error = ClientVerifyServerTls::from_parts(RootCertStore::empty());
+    assert!(matches!(error.err(), Some(TlsError::InvalidCa)));
+}
+
+#[tokio::test]
+async fn connect_with_name_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "wrong.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    assert!(connector.connect(client_io).await.is_err());
+    assert!(server_task.await.unwrap().is_err());
+
+    let (server_io, client_io) = duplex(4096);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let _client_io = connector
+        .connect_with_name("s1.testing-server.playit.cloud", client_io)
+        .await
+        .unwrap();
+    let server_io = server_task.await.unwrap().unwrap();
+    assert_eq!(server_io.get_ref().1.server_name(), Some("s1.testing-server.playit.cloud"));
+
+    let name = ServerName::try_from("s1.testing-server.playit.cloud").unwrap();
+    let named = ClientConnector::from_server_name(
+        name,
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    );
+    let (server_io, client_io) = duplex(4096);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let _client_io = named.connect(client_io).await.unwrap();
+    server_task.await.unwrap().unwrap();
+
+    let (_, client_io) = duplex(4096);
+    let error = connector.connect_with_name("not a name", client_io).await.err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn combined_pem_key_test() {
+    crate::install_crypto().unwrap();
+    let key = include_bytes!("./res/server.key");
+    let chain = include_bytes!("./res/server.crt");
+    let combined = [&chain[..], b"\n", &key[..]].concat();
+
+    let acceptor = TlsSetup::build_server(&combined, &[])
+        .unwrap()
+        .into_acceptor()
+        .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let _client_io = connector.connect(client_io).await.unwrap();
+    server_task.await.unwrap().unwrap();
+
+    let two_keys = [&combined[..], b"\n", &include_bytes!("./res/client.key")[..]].concat();
+    let error = TlsSetup::build_server(&two_keys, chain).err().unwrap();
+    assert!(matches!(error, TlsError::MultiplePrivateKeys(2)));
+
+    let error = TlsSetup::build_server(chain, chain).err().unwrap();
+    assert!(matches!(error, TlsError::NoPrivateKey));
+}
+
+type RecordedHandshake = (HandshakeDirection, Option<String>, Option<ProtocolVersion>, bool);
+
+#[derive(Default)]
+struct RecordHandshakes {
+    events: Mutex<Vec<RecordedHandshake>>,
+}
+
+impl HandshakeObserver for RecordHandshakes {
+    fn on_complete(&self, event: &HandshakeEvent<'_>) {
+        self.events.lock().unwrap().push((
+            event.direction,
+            event.peer_name.map(str::to_string),
+            event.protocol_version,
+            true,
+        ));
+    }
+
+    fn on_error(&self, event: &HandshakeEvent<'_>, _error: &std::io::Error) {
+        self.events.lock().unwrap().push((
+            event.direction,
+            event.peer_name.map(str::to_string),
+            event.protocol_version,
+            false,
+        ));
+    }
+}
+
+#[tokio::test]
+async fn handshake_observer_test() {
+    crate::install_crypto().unwrap();
+    let observer = Arc::new(RecordHandshakes::default());
+
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    )
+    .with_observer(observer.clone());
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap()
+    .with_observer(observer.clone());
+
+    let (server_io, client_io) = duplex(4096);
+    let server_task = {
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move { acceptor.accept(server_io).await })
+    };
+    let _client_io = connector.connect(client_io).await.unwrap();
+    let _server_io = server_task.await.unwrap().unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let server_task = tokio::spawn(async move { acceptor.accept(server_io).await });
+    assert!(connector.connect_with_name("wrong.playit.cloud", client_io).await.is_err());
+    assert!(server_task.await.unwrap().is_err());
+
+    let mut events = std::mem::take(&mut *observer.events.lock().unwrap());
+    events.sort_by_key(|event| (!event.3, event.0 == HandshakeDirection::Server));
+
+    let name = Some("s1.testing-server.playit.cloud".to_string());
+    let version = Some(ProtocolVersion::TLSv1_3);
+    assert_eq!(
+        events,
+        vec![
+            (HandshakeDirection::Client, name.clone(), version, true),
+            (HandshakeDirection::Server, name, version, true),
+            (HandshakeDirection::Client, Some("wrong.playit.cloud".to_string()), None, false),
+            (HandshakeDirection::Server, Some("wrong.playit.cloud".to_string()), None, false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn server_with_chain_test() {
+    crate::install_crypto().unwrap();
+    let full_chain = std::str::from_utf8(include_bytes!("./res/server.crt")).unwrap();
+    let mut certs: Vec<String> = full_chain
+        .split_inclusive("-----END CERTIFICATE-----")
+        .filter(|pem| pem.contains("-----BEGIN CERTIFICATE-----"))
+        .map(str::to_string)
+        .collect();
+    assert_eq!(certs.len(), 3);
+
+    let leaf = certs.remove(0);
+    certs.reverse();
+    let intermediates = certs.concat();
+
+    let acceptor = TlsSetup::build_server_with_chain(
+        include_bytes!("./res/server.key"),
+        leaf.as_bytes(),
+        intermediates.as_bytes(),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let client_io = connector.connect(client_io).await.unwrap();
+    server_task.await.unwrap().unwrap();
+    assert_eq!(client_io.peer_certificates().unwrap().len(), 3);
+
+    // fullchain.pem plus chain.pem, and a combined key and chain with no cert_data
+    let expected = crate::tls_setup::parse_certificates(full_chain.as_bytes()).unwrap();
+    let combined = [&include_bytes!("./res/server.key")[..], full_chain.as_bytes()].concat();
+    for (key, cert) in [
+        (&include_bytes!("./res/server.key")[..], full_chain.as_bytes()),
+        (&combined[..], &b""[..]),
+    ] {
+        let acceptor = TlsSetup::build_server_with_chain(key, cert, intermediates.as_bytes())
+            .unwrap()
+            .into_acceptor()
+            .unwrap();
+        let (server_io, client_io) = duplex(8192);
+        let server_task = tokio::spawn(acceptor.accept(server_io));
+        let client_io = connector.connect(client_io).await.unwrap();
+        server_task.await.unwrap().unwrap();
+        assert_eq!(client_io.peer_certificates(), Some(&expected[..]));
+    }
+
+    let unrelated = [intermediates.as_bytes(), include_bytes!("./res/client.crt")].concat();
+    let error = TlsSetup::build_server_with_chain(
+        include_bytes!("./res/server.key"),
+        leaf.as_bytes(),
+        &unrelated,
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(error, TlsError::InvalidChain(_)));
+}
+
+#[tokio::test]
+async fn connect_with_retry_test() {
+    crate::install_crypto().unwrap();
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let closed_addr = closed.local_addr().unwrap();
+    drop(closed);
+
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(20),
+        jitter: Some(Duration::from_millis(5)),
+    };
+
+    let start = std::time::Instant::now();
+    let error = ClientConnector::plain()
+        .connect_with_retry(closed_addr, &policy)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused);
+    assert!(start.elapsed() >= Duration::from_millis(60));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+    let stream = ClientConnector::plain().connect_with_retry(addr, &policy).await.unwrap();
+    assert!(stream.is_plaintext());
+    server_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn timeout_stream_test() {
+    crate::install_crypto().unwrap();
+    let (server_io, client_io) = duplex(16);
+    let mut client_io = ClientConnector::plain()
+        .connect(client_io)
+        .await
+        .unwrap()
+        .with_timeouts(Duration::from_millis(50), Duration::from_millis(50));
+    let mut server_io = ClientAcceptor::plain().accept(server_io).await.unwrap();
+
+    let mut buf = [0u8; 4];
+    let error = client_io.read_exact(&mut buf).await.err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+    server_io.write_all(b"ping").await.unwrap();
+    client_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+
+    let error = client_io.write_all(&[0u8; 64]).await.err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+    let mut received = [0u8; 16];
+    server_io.read_exact(&mut received).await.unwrap();
+}
+
+#[tokio::test]
+async fn plaintext_fallback_test() {
+    crate::install_crypto().unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move {
+        let (mut first, _) = listener.accept().await.unwrap();
+        let mut hello = [0u8; 1024];
+        let _ = first.read(&mut hello).await.unwrap();
+        first.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await.unwrap();
+        drop(first);
+
+        let (mut second, _) = listener.accept().await.unwrap();
+        second.write_all(b"plain").await.unwrap();
+        second
+    });
+
+    let mut client_io = connector
+        .connect_with_plaintext_fallback(addr, PlaintextFallback::default())
+        .await
+        .unwrap();
+    assert!(client_io.is_plaintext());
+    let mut buf = [0u8; 5];
+    client_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"plain");
+    let _server_io = server_task.await.unwrap();
+
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/client.key"),
+        include_bytes!("./res/client.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let _ = acceptor.accept(tcp).await;
+        listener
+    });
+
+    let error = connector
+        .connect_with_plaintext_fallback(addr, PlaintextFallback::default())
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    server_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn vectored_write_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut plain = ClientAcceptor::plain().accept(tcp).await.unwrap();
+        assert!(plain.is_write_vectored());
+        let mut buf = [0u8; 6];
+        plain.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"header");
+
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut tls = acceptor.accept(tcp).await.unwrap();
+        let mut buf = [0u8; 13];
+        tls.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"headerpayload");
+    });
+
+    let bufs = [std::io::IoSlice::new(b"head"), std::io::IoSlice::new(b"er")];
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut plain = MaybeTlsStream::Client(ClientConnector::plain().connect(tcp).await.unwrap());
+    assert!(plain.is_write_vectored());
+    let written = plain.write_vectored(&bufs).await.unwrap();
+    plain.write_all(&b"header"[written..]).await.unwrap();
+    plain.flush().await.unwrap();
+
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut tls = connector.connect(tcp).await.unwrap();
+    let bufs = [std::io::IoSlice::new(b"header"), std::io::IoSlice::new(b"payload")];
+    let written = tls.write_vectored(&bufs).await.unwrap();
+    tls.write_all(&b"headerpayload"[written..]).await.unwrap();
+    tls.flush().await.unwrap();
+
+    server_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn crl_revocation_test() {
+    crate::install_crypto().unwrap();
+    let crls = parse_crls(include_bytes!("./res/p256.crl")).unwrap();
+    let acceptor = TlsSetup::build_mutual(
+        include_bytes!("./res/p256_ca.crt"),
+        include_bytes!("./res/p256.pem"),
+        b"",
+    )
+    .unwrap()
+    .with_crls(crls)
+    .into_acceptor()
+    .unwrap();
+
+    for (identity, should_connect) in [
+        (&include_bytes!("./res/p256.pem")[..], true),
+        (&include_bytes!("./res/p256_revoked.pem")[..], false),
+    ] {
+        let connector = TlsSetup::build_mutual(include_bytes!("./res/p256_ca.crt"), identity, b"")
+            .unwrap()
+            .into_connector()
+            .unwrap();
+
+        let (server_io, client_io) = duplex(4096);
+        let server_task = tokio::spawn(acceptor.accept(server_io));
+        let client_result = ClientConnector::tls("s1.testing-p256.playit.cloud", connector)
+            .unwrap()
+            .connect(client_io)
+            .await;
+
+        let mut client_io = client_result.unwrap();
+        let server_result = server_task.await.unwrap();
+        assert_eq!(server_result.is_ok(), should_connect);
+
+        if !should_connect {
+            let mut buf = [0u8; 1];
+            assert!(client_io.read(&mut buf).await.is_err());
+        }
+    }
+
+    let error = parse_crls(include_bytes!("./res/server.crt")).err().unwrap();
+    assert!(matches!(error, TlsError::InvalidCrl(_)));
+}
+
+#[tokio::test]
+async fn allowed_client_cns_test() {
+    crate::install_crypto().unwrap();
+    for (allowed, should_connect) in [
+        ("c1.testing-client.playit.cloud", true),
+        ("c2.testing-client.playit.cloud", false),
+    ] {
+        let acceptor = TlsSetup::build_mutual(
+            include_bytes!("./res/client_ca.crt"),
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .with_allowed_client_cns([allowed.to_string()].into_iter().collect())
+        .into_acceptor()
+        .unwrap();
+        let connector = TlsSetup::build_mutual(
+            include_bytes!("./res/server_ca.crt"),
+            include_bytes!("./res/client.key"),
+            include_bytes!("./res/client.crt"),
+        )
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+        let (server_io, client_io) = duplex(4096);
+        let server_task = tokio::spawn(acceptor.accept(server_io));
+        let _client_io = ClientConnector::tls("s1.testing-server.playit.cloud", connector)
+            .unwrap()
+            .connect(client_io)
+            .await
+            .unwrap();
+
+        assert_eq!(server_task.await.unwrap().is_ok(), should_connect);
+    }
+}
+
+#[tokio::test]
+async fn close_notify_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let server_task = {
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            let mut server_io = acceptor.accept(server_io).await.unwrap();
+            server_io.write_all(b"unread").await.unwrap();
+            let mut buf = Vec::new();
+            server_io.read_to_end(&mut buf).await.unwrap();
+            server_io.shutdown().await.unwrap();
+        })
+    };
+    let mut client_io = connector.connect(client_io).await.unwrap();
+    client_io.close().await.unwrap();
+    server_task.await.unwrap();
+
+    let (server_io, client_io) = duplex(4096);
+    let server_task = tokio::spawn(async move {
+        let mut server_io = acceptor.accept(server_io).await.unwrap();
+        let mut buf = [0u8; 1];
+        let _ = server_io.read(&mut buf).await;
+        drop(server_io.into_inner());
+    });
+    let mut client_io = connector.connect(client_io).await.unwrap();
+    let error = client_io.close().await.err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    server_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn lets_encrypt_combined_pem_test() {
+    crate::install_crypto().unwrap();
+    let privkey = include_bytes!("./res/server.key");
+    let fullchain = include_bytes!("./res/server.crt");
+    let combined = [&privkey[..], &fullchain[..]].concat();
+
+    let path = std::env::temp_dir().join(format!("tls-friend-{}-combined.pem", std::process::id()));
+    tokio::fs::write(&path, &combined).await.unwrap();
+    let server = TlsSetup::load_server(path.to_str().unwrap()).await;
+    tokio::fs::remove_file(&path).await.unwrap();
+
+    let acceptor = server.unwrap().into_acceptor().unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let client_io = connector.connect(client_io).await.unwrap();
+    server_task.await.unwrap().unwrap();
+
+    let expected = crate::tls_setup::parse_certificates(fullchain).unwrap();
+    assert_eq!(expected.len(), 3);
+    assert_eq!(client_io.peer_certificates().unwrap(), &expected[..]);
+}
+
+#[tokio::test]
+async fn server_name_override_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move {
+        for _ in 0..3 {
+            let (stream, _) = listener.accept().await.unwrap();
+            let _ = acceptor.accept(stream).await;
+        }
+    });
+
+    let client = ClientConnector::tls("s1.testing-server.playit.cloud", connector.clone()).unwrap();
+    ConnectionBuilder::connect(&client, addr).await.unwrap();
+
+    let target = format!("127.0.0.1:{}", addr.port());
+    assert!(HostConnectionBuilder::tls(connector.clone()).connect(&target).await.is_err());
+    HostConnectionBuilder::tls(connector)
+        .with_server_name("s1.testing-server.playit.cloud")
+        .connect(&target)
+        .await
+        .unwrap();
+
+    server_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn load_client_dir_test() {
+    crate::install_crypto().unwrap();
+    let dir = std::env::temp_dir().join(format!("tls-friend-{}-trust.d", std::process::id()));
+    tokio::fs::create_dir_all(dir.join("nested")).await.unwrap();
+    let dir_str = dir.to_str().unwrap().to_string();
+
+    tokio::fs::write(dir.join("broken.pem"), b"not a pem").await.unwrap();
+    tokio::fs::write(dir.join("notes.txt"), include_bytes!("./res/server_ca.crt")).await.unwrap();
+    tokio::fs::write(dir.join("nested/server_ca.crt"), include_bytes!("./res/server_ca.crt"))
+        .await
+        .unwrap();
+    let empty = TlsSetup::load_client_dir(&dir_str).await;
+
+    tokio::fs::write(dir.join("client_ca.pem"), include_bytes!("./res/client_ca.crt"))
+        .await
+        .unwrap();
+    tokio::fs::write(dir.join("server_ca.crt"), include_bytes!("./res/server_ca.crt"))
+        .await
+        .unwrap();
+    let loaded = TlsSetup::load_client_dir(&dir_str).await;
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+    assert!(matches!(empty.err().unwrap(), TlsError::InvalidCa));
+
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        loaded.unwrap().into_connector().unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let _client_io = connector.connect(client_io).await.unwrap();
+    server_task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn no_sni_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let client = ClientConnector::tls("s1.testing-server.playit.cloud", connector.clone()).unwrap();
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+    client_io.unwrap();
+    assert_eq!(server_io.unwrap().server_name(), Some("s1.testing-server.playit.cloud"));
+
+    let (server_io, client_io) = duplex(8192);
+    let client = ClientConnector::tls_no_sni("s1.testing-server.playit.cloud", connector.clone())
+        .unwrap();
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+    client_io.unwrap();
+    assert_eq!(server_io.unwrap().server_name(), None);
+
+    let (server_io, client_io) = duplex(8192);
+    let client = ClientConnector::tls_no_sni("other.playit.cloud", connector).unwrap();
+    let (_, client_io) = tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+    assert!(client_io.is_err());
+}
+
+#[tokio::test]
+async fn tls_info_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_mutual(
+            include_bytes!("./res/client_ca.crt"),
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .with_alpn(vec![b"h2".to_vec()])
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_mutual(
+            include_bytes!("./res/server_ca.crt"),
+            include_bytes!("./res/client.key"),
+            include_bytes!("./res/client.crt"),
+        )
+        .unwrap()
+        .with_alpn(vec![b"h2".to_vec()])
+        .into_connector()
+        .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+    let (server_io, client_io) = (server_io.unwrap(), client_io.unwrap());
+
+    let client_info = client_io.tls_info().unwrap();
+    assert_eq!(client_info.version, ProtocolVersion::TLSv1_3);
+    assert_eq!(client_info.alpn.as_deref(), Some(&b"h2"[..]));
+    assert_eq!(client_info.server_name, None);
+    assert_eq!(client_info.peer_certificates.len(), 3);
+    assert_eq!(
+        client_info.cipher_suite.suite(),
+        client_io.negotiated_cipher_suite().unwrap().suite()
+    );
+
+    let server_info = MaybeTlsStream::Server(server_io).tls_info().unwrap();
+    assert_eq!(server_info.server_name.as_deref(), Some("s1.testing-server.playit.cloud"));
+    assert_eq!(server_info.peer_certificates.len(), 3);
+
+    let (_, plain_io) = duplex(1024);
+    let plain_io = ClientConnector::plain().connect(plain_io).await.unwrap();
+    assert!(plain_io.tls_info().is_none());
+}
+
+#[test]
+fn bom_crlf_bundle_test() {
+    crate::install_crypto().unwrap();
+    let chain = std::str::from_utf8(include_bytes!("./res/server.crt")).unwrap();
+    let key = std::str::from_utf8(include_bytes!("./res/server.key")).unwrap();
+    let bundle = format!(
+        "\u{feff}subject=CN = s1.testing-server.playit.cloud\nissuer=CN = intermediate\n{}",
+        chain
+    )
+    .replace('\n', "\r\n");
+
+    let certs = crate::tls_setup::parse_certificates(bundle.as_bytes()).unwrap();
+    assert_eq!(certs, crate::tls_setup::parse_certificates(chain.as_bytes()).unwrap());
+
+    let key = format!("\u{feff}{}", key).replace('\n', "\r\n");
+    TlsSetup::build_server(key.as_bytes(), bundle.as_bytes()).unwrap();
+    TlsSetup::build_client(format!("\u{feff}{}", chain).as_bytes()).unwrap();
+
+    let encrypted = std::str::from_utf8(include_bytes!("./res/server_encrypted.key")).unwrap();
+    let encrypted = format!("\u{feff}{}", encrypted).replace('\n', "\r\n");
+    TlsSetup::build_mutual_with_password(
+        include_bytes!("./res/client_ca.crt"),
+        encrypted.as_bytes(),
+        bundle.as_bytes(),
+        "hunter2",
+    )
+    .unwrap();
+}
+
+#[cfg(feature = "test-util")]
+#[tokio::test]
+async fn generate_self_signed_test() {
+    crate::install_crypto().unwrap();
+    let (server, client) = crate::testing::generate_self_signed(&["api.internal", "127.0.0.1"]);
+
+    let acceptor = server
+        .with_allowed_client_cns([crate::testing::CLIENT_NAME.to_string()].into())
+        .into_acceptor()
+        .unwrap();
+    let connector = client.into_connector().unwrap();
+
+    for name in ["api.internal", "127.0.0.1"] {
+        let client = ClientConnector::tls(name, connector.clone()).unwrap();
+        let (server_io, client_io) = duplex(8192);
+        let (server_io, client_io) =
+            tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+        server_io.unwrap();
+        client_io.unwrap();
+    }
+
+    let client = ClientConnector::tls("other.internal", connector).unwrap();
+    let (server_io, client_io) = duplex(8192);
+    let (_, client_io) = tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+    assert!(client_io.is_err());
+}
+
+#[tokio::test]
+async fn max_fragment_size_test() {
+    crate::install_crypto().unwrap();
+    let server = || {
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+    };
+
+    let config = server().with_max_fragment_size(Some(512)).into_server_config().unwrap();
+    assert_eq!(config.max_fragment_size, Some(512));
+
+    for size in [31, 16385] {
+        let error = server().with_max_fragment_size(Some(size)).into_acceptor().err().unwrap();
+        assert!(matches!(error, TlsError::InvalidFragmentSize(_)));
+        assert_eq!(std::io::Error::from(error).kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    let acceptor = server().with_max_fragment_size(Some(512)).into_acceptor().unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+    let (mut server_io, mut client_io) = (server_io.unwrap(), client_io.unwrap());
+
+    let payload = vec![7u8; 4096];
+    let mut received = vec![0u8; payload.len()];
+    let (written, read) =
+        tokio::join!(server_io.write_all(&payload), client_io.read_exact(&mut received));
+    written.unwrap();
+    read.unwrap();
+    assert_eq!(received, payload);
+}
+
+#[tokio::test]
+async fn counting_stream_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let client_io = connector.connect(client_io).await.unwrap();
+    let mut server_io = server_task.await.unwrap().unwrap();
+
+    let (mut client_io, counters) = client_io.counted();
+    let reader = tokio::spawn(async move {
+        let mut buf = [0u8; 5];
+        server_io.read_exact(&mut buf).await.unwrap();
+        server_io.write_all(b"hello world").await.unwrap();
+        server_io.flush().await.unwrap();
+        server_io
+    });
+
+    client_io.write_all(b"hello").await.unwrap();
+    client_io.flush().await.unwrap();
+    let mut buf = [0u8; 11];
+    client_io.read_exact(&mut buf).await.unwrap();
+    let _server_io = reader.await.unwrap();
+
+    assert_eq!(counters.bytes_written(), 5);
+    assert_eq!(counters.bytes_read(), 11);
+    assert_eq!(client_io.bytes_read(), 11);
+    assert!(client_io.get_ref().is_encrypted());
+}
+
+#[tokio::test]
+async fn accept_lazy_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let pending = acceptor.accept_lazy(server_io);
+    assert!(pending.needs_handshake());
+
+    let worker = tokio::spawn(pending.handshake());
+    let mut client_io = connector.connect(client_io).await.unwrap();
+    let mut server_io = worker.await.unwrap().unwrap();
+    assert!(server_io.is_encrypted());
+
+    client_io.write_all(b"ping").await.unwrap();
+    client_io.flush().await.unwrap();
+    let mut buf = [0u8; 4];
+    server_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+
+    let (server_io, _client_io) = duplex(8192);
+    let pending = ClientAcceptor::plain().accept_lazy(server_io);
+    assert!(!pending.needs_handshake());
+    assert!(pending.handshake().await.unwrap().is_plaintext());
+}
+
+#[test]
+fn rustls_error_test() {
+    crate::install_crypto().unwrap();
+    let error = TlsError::from(rustls::Error::InconsistentKeys(
+        rustls::InconsistentKeys::KeyMismatch,
+    ));
+
+    let error = std::io::Error::from(error);
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    let inner = error.get_ref().unwrap().downcast_ref::<TlsError>().unwrap();
+    assert!(matches!(inner, TlsError::Rustls(rustls::Error::InconsistentKeys(_))));
+}
+
+/// Stands in for an HSM backed key: signs through the wrapped key but cannot export its
+/// public key.
+#[derive(Debug)]
+struct OpaqueSigningKey(Arc<dyn rustls::sign::SigningKey>);
+
+impl rustls::sign::SigningKey for OpaqueSigningKey {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn rustls::sign::Signer>> {
+        self.0.choose_scheme(offered)
+    }
+
+    fn algorithm(&self) -> rustls::SignatureAlgorithm {
+        self.0.algorithm()
+    }
+}
+
+#[tokio::test]
+async fn signing_key_test() {
+    crate::install_crypto().unwrap();
+    let load = |pem: &[u8]| {
+        let key = rustls_pemfile::private_key(&mut &pem[..]).unwrap().unwrap();
+        default_provider().key_provider.load_private_key(key).unwrap()
+    };
+    let chain = |pem: &[u8]| {
+        rustls_pemfile::certs(&mut &pem[..]).collect::<Result<Vec<_>, _>>().unwrap()
+    };
+
+    let trust = |pem: &[u8]| {
+        let mut trust = RootCertStore::empty();
+        trust.add_parsable_certificates(chain(pem));
+        trust
+    };
+
+    let server_key: Arc<dyn rustls::sign::SigningKey> =
+        Arc::new(OpaqueSigningKey(load(include_bytes!("./res/server.key"))));
+    let acceptor = MutualTls::from_signing_key(
+        trust(include_bytes!("./res/client_ca.crt")),
+        chain(include_bytes!("./res/server.crt")),
+        server_key,
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+
+    let client_key: Arc<dyn rustls::sign::SigningKey> =
+        Arc::new(OpaqueSigningKey(load(include_bytes!("./res/client.key"))));
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        MutualTls::from_signing_key(
+            trust(include_bytes!("./res/server_ca.crt")),
+            chain(include_bytes!("./res/client.crt")),
+            client_key,
+        )
+        .unwrap()
+        .into_connector()
+        .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+    assert_eq!(server_io.unwrap().get_ref().1.peer_certificates().unwrap().len(), 3);
+    client_io.unwrap();
+
+    let error = OpenServerTls::from_signing_key(
+        chain(include_bytes!("./res/server.crt")),
+        load(include_bytes!("./res/client.key")),
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(error, TlsError::KeyMismatch));
+}
+
+#[derive(Default)]
+struct RecordOfferedAlpn {
+    offers: Mutex<Vec<(Vec<Vec<u8>>, bool)>>,
+}
+
+impl HandshakeObserver for RecordOfferedAlpn {
+    fn on_complete(&self, event: &HandshakeEvent<'_>) {
+        self.offers.lock().unwrap().push((event.offered_alpn.to_vec(), true));
+    }
+
+    fn on_error(&self, event: &HandshakeEvent<'_>, _error: &std::io::Error) {
+        self.offers.lock().unwrap().push((event.offered_alpn.to_vec(), false));
+    }
+}
+
+#[tokio::test]
+async fn offered_alpn_test() {
+    crate::install_crypto().unwrap();
+    let observer = Arc::new(RecordOfferedAlpn::default());
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .with_alpn(vec![b"h2".to_vec()])
+        .into_acceptor()
+        .unwrap(),
+    )
+    .with_observer(observer.clone());
+    let connector = |alpn: Vec<Vec<u8>>| {
+        ClientConnector::tls(
+            "s1.testing-server.playit.cloud",
+            TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+                .unwrap()
+                .with_alpn(alpn)
+                .into_connector()
+                .unwrap(),
+        )
+        .unwrap()
+    };
+
+    let client = connector(vec![b"http/1.1".to_vec(), b"h2".to_vec()]);
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+    assert_eq!(server_io.unwrap().alpn_protocol(), Some(&b"h2"[..]));
+    client_io.unwrap();
+
+    let client = connector(vec![b"http/1.1".to_vec()]);
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+    assert!(server_io.is_err());
+    assert!(client_io.is_err());
+
+    assert_eq!(
+        *observer.offers.lock().unwrap(),
+        vec![
+            (vec![b"http/1.1".to_vec(), b"h2".to_vec()], true),
+            (vec![b"http/1.1".to_vec()], false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn protocol_versions_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .with_protocol_versions(&[&rustls::version::TLS12])
+    .into_acceptor()
+    .unwrap();
+    let connector = |versions: &[&'static rustls::SupportedProtocolVersion]| {
+        ClientConnector::tls(
+            "s1.testing-server.playit.cloud",
+            TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+                .unwrap()
+                .with_protocol_versions(versions)
+                .into_connector()
+                .unwrap(),
+        )
+        .unwrap()
+    };
+
+    let client = connector(&[&rustls::version::TLS12, &rustls::version::TLS13]);
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+    server_io.unwrap();
+    assert_eq!(client_io.unwrap().protocol_version(), Some(ProtocolVersion::TLSv1_2));
+
+    let client = connector(&[&rustls::version::TLS13]);
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+    assert!(server_io.is_err());
+    assert!(client_io.is_err());
+}
+
+#[test]
+fn legacy_encrypted_key_test() {
+    crate::install_crypto().unwrap();
+    let build = |key: &[u8], cert: &[u8], password: &str| {
+        TlsSetup::build_mutual_with_password(
+            include_bytes!("./res/client_ca.crt"),
+            key,
+            cert,
+            password,
+        )
+    };
+    let rsa = include_bytes!("./res/server_legacy.key");
+    let ec = include_bytes!("./res/p256_legacy.key");
+
+    let error = TlsSetup::build_server(rsa, include_bytes!("./res/server.crt")).err().unwrap();
+    assert!(matches!(error, TlsError::InvalidKey(_)));
+
+    #[cfg(feature = "legacy-encrypted-keys")]
+    {
+        build(rsa, include_bytes!("./res/server.crt"), "hunter2")
+            .unwrap()
+            .into_acceptor()
+            .unwrap();
+        build(ec, include_bytes!("./res/p256.pem"), "hunter2")
+            .unwrap()
+            .into_acceptor()
+            .unwrap();
+
+        let error = build(rsa, include_bytes!("./res/server.crt"), "wrong").err().unwrap();
+        assert!(matches!(error, TlsError::WrongPassword));
+    }
+
+    #[cfg(not(feature = "legacy-encrypted-keys"))]
+    for (key, cert) in [
+        (&rsa[..], &include_bytes!("./res/server.crt")[..]),
+        (&ec[..], &include_bytes!("./res/p256.pem")[..]),
+    ] {
+        let error = build(key, cert, "hunter2").err().unwrap();
+        assert!(matches!(error, TlsError::UnsupportedKeyEncryption(_)));
+    }
+}
+
+#[tokio::test]
+async fn into_server_name_test() {
+    crate::install_crypto().unwrap();
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+
+    let name = String::from("s1.testing-server.playit.cloud");
+    assert!(ClientConnector::tls(&name, connector.clone()).is_ok());
+    assert!(ClientConnector::tls(name, connector.clone()).is_ok());
+    assert!(ClientConnector::tls(
+        ServerName::try_from("s1.testing-server.playit.cloud").unwrap(),
+        connector.clone()
+    )
+    .is_ok());
+    assert!(ClientConnector::tls(
+        std::net::IpAddr::from([127, 0, 0, 1]),
+        connector.clone()
+    )
+    .is_ok());
+
+    let Err(error) = ClientConnector::tls("not a name", connector) else {
+        panic!("expected invalid server name");
+    };
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(error.to_string().contains("not a name"));
+}
+
+#[tokio::test]
+async fn buffered_stream_test() {
+    use tokio::io::AsyncBufReadExt;
+
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let server_task = tokio::spawn(acceptor.accept(server_io));
+    let client_io = connector.connect(client_io).await.unwrap();
+    let mut server_io = server_task.await.unwrap().unwrap();
+
+    let writer = tokio::spawn(async move {
+        server_io.write_all(b"EHLO one\r\nEHLO two\r\n").await.unwrap();
+        server_io.flush().await.unwrap();
+        let mut buf = [0u8; 4];
+        server_io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"QUIT");
+        server_io
+    });
+
+    let mut client_io = client_io.buffered();
+    let mut line = String::new();
+    client_io.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "EHLO one\r\n");
+    line.clear();
+    client_io.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "EHLO two\r\n");
+
+    client_io.write_all(b"QUIT").await.unwrap();
+    client_io.flush().await.unwrap();
+    let _server_io = writer.await.unwrap();
+    assert!(client_io.get_ref().is_encrypted());
+}
+
+#[tokio::test]
+async fn accept_detect_peek_buffer_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    for peek_buffer in [0, 1, 4096] {
+        let (server_io, client_io) = duplex(8192);
+        let (server_io, client_io) = tokio::join!(
+            acceptor.accept_detect_with_peek_buffer(server_io, peek_buffer),
+            connector.connect(client_io)
+        );
+        let mut client_io = client_io.unwrap();
+        let mut server_io = server_io.unwrap();
+        assert!(server_io.is_encrypted());
+
+        client_io.write_all(b"hello").await.unwrap();
+        client_io.flush().await.unwrap();
+        let mut buf = [0u8; 5];
+        server_io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    let (server_io, mut client_io) = duplex(4096);
+    client_io.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    let mut server_io = acceptor.accept_detect_with_peek_buffer(server_io, 4).await.unwrap();
+    assert!(server_io.is_plaintext());
+
+    let mut buf = [0u8; 16];
+    server_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"GET / HTTP/1.1\r\n");
+}
+
+#[tokio::test]
+async fn reconnecting_stream_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut first = acceptor.accept(stream).await.unwrap();
+        first.write_all(b"one").await.unwrap();
+        first.shutdown().await.unwrap();
+        drop(first);
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut second = acceptor.accept(stream).await.unwrap();
+        let mut buf = [0u8; 4];
+        second.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"sync");
+        second.write_all(b"two").await.unwrap();
+        second.shutdown().await.unwrap();
+    });
+
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(20),
+        jitter: None,
+    };
+    let mut stream = ReconnectingStream::connect(connector, addr, policy).await.unwrap();
+
+    let mut buf = [0u8; 3];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"one");
+
+    let error = stream.read(&mut buf).await.unwrap_err();
+    assert!(matches!(
+        ReconnectError::from_io_error(&error),
+        Some(ReconnectError::Reconnected { attempts: 1, .. })
+    ));
+    assert_eq!(stream.reconnects(), 1);
+
+    stream.write_all(b"sync").await.unwrap();
+    stream.flush().await.unwrap();
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"two");
+    server_task.await.unwrap();
+
+    let error = stream.read(&mut buf).await.unwrap_err();
+    assert!(matches!(
+        ReconnectError::from_io_error(&error),
+        Some(ReconnectError::Failed { attempts: 3, .. })
+    ));
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused);
+    assert!(stream.get_ref().is_none());
+}
+
+#[tokio::test]
+async fn hardened_test() {
+    crate::install_crypto().unwrap();
+    let config = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .hardened()
+    .into_server_config()
+    .unwrap();
+    assert_eq!(config.send_tls13_tickets, 0);
+    assert_eq!(config.max_early_data_size, 0);
+    assert!(config
+        .crypto_provider()
+        .cipher_suites
+        .iter()
+        .all(|suite| suite.version() == &rustls::version::TLS13));
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let client_config = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .hardened()
+        .with_early_data()
+        .into_client_config()
+        .unwrap();
+    assert!(!client_config.enable_early_data);
+    let client = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsConnector::from(Arc::new(client_config)),
+    )
+    .unwrap();
+
+    for _ in 0..2 {
+        let (server_io, client_io) = duplex(8192);
+        let (server_io, client_io) =
+            tokio::join!(acceptor.accept(server_io), client.connect(client_io));
+        let server_io = server_io.unwrap();
+        assert_eq!(client_io.unwrap().protocol_version(), Some(ProtocolVersion::TLSv1_3));
+        assert_eq!(server_io.get_ref().1.handshake_kind(), Some(HandshakeKind::Full));
+    }
+
+    let tls12_client = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .with_protocol_versions(&[&rustls::version::TLS12])
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), tls12_client.connect(client_io));
+    assert!(server_io.is_err());
+    assert!(client_io.is_err());
+}
+
+#[tokio::test]
+async fn hardened_overrides_test() {
+    crate::install_crypto().unwrap();
+    let all_suites = rustls::crypto::CryptoProvider::get_default().unwrap().cipher_suites.clone();
+    let server = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .hardened()
+    .with_ticketer()
+    .with_protocol_versions(&[&rustls::version::TLS12, &rustls::version::TLS13])
+    .with_cipher_suites(all_suites);
+    assert_eq!(server.active_protocol_versions().unwrap(), vec![&rustls::version::TLS13]);
+    assert!(server
+        .active_cipher_suites()
+        .unwrap()
+        .iter()
+        .all(|suite| suite.version() == &rustls::version::TLS13));
+    let acceptor = server.into_acceptor().unwrap();
+
+    let client_config = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .hardened()
+        .with_session_cache(16)
+        .with_early_data()
+        .into_client_config()
+        .unwrap();
+    assert!(!client_config.enable_early_data);
+    let client = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsConnector::from(Arc::new(client_config)),
+    )
+    .unwrap();
+
+    for kind in [HandshakeKind::Full, HandshakeKind::Resumed] {
+        let (server_io, client_io) = duplex(8192);
+        let server_task = {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let mut server_io = acceptor.accept(server_io).await.unwrap();
+                server_io.write_all(b"ok").await.unwrap();
+                server_io.flush().await.unwrap();
+                server_io
+            })
+        };
+
+        let mut client_io = client.connect(client_io).await.unwrap();
+        let mut buf = [0u8; 2];
+        client_io.read_exact(&mut buf).await.unwrap();
+        let server_io = server_task.await.unwrap();
+        assert_eq!(server_io.get_ref().1.handshake_kind(), Some(kind));
+    }
+}
+
+#[tokio::test]
+async fn received_close_notify_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    for clean in [true, false] {
+        let client_io = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (server_io, _) = listener.accept().await.unwrap();
+        let (server_io, client_io) =
+            tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+        let mut server_io = server_io.unwrap();
+        let mut client_io = client_io.unwrap();
+
+        server_io.write_all(b"payload").await.unwrap();
+        if clean {
+            server_io.shutdown().await.unwrap();
+        } else {
+            server_io.flush().await.unwrap();
+            drop(server_io.into_inner());
+        }
+
+        let mut buf = Vec::new();
+        let result = client_io.read_to_end(&mut buf).await;
+        assert_eq!(buf, b"payload");
+        assert_eq!(result.is_ok(), clean);
+        assert_eq!(client_io.received_close_notify(), clean);
+    }
+
+    let (_server_io, client_io) = duplex(64);
+    let mut plain = ClientStream::TcpStream(client_io);
+    assert!(!plain.received_close_notify());
+}
+
+#[tokio::test]
+async fn shared_config_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::from_server_tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let accepted = acceptor.clone();
+    let connected = connector.clone();
+    assert!(Arc::ptr_eq(
+        acceptor.server_config().unwrap(),
+        accepted.server_config().unwrap()
+    ));
+    assert!(Arc::ptr_eq(
+        connector.client_config().unwrap(),
+        connected.client_config().unwrap()
+    ));
+    assert!(ClientAcceptor::plain().server_config().is_none());
+
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(accepted.accept(server_io), connected.connect(client_io));
+    assert!(server_io.unwrap().is_encrypted());
+    assert!(client_io.unwrap().is_encrypted());
+}
+
+#[tokio::test]
+async fn setup_connect_accept_test() {
+    crate::install_crypto().unwrap();
+    let server: ServerTls = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into();
+    let client: ClientTls =
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt")).unwrap().into();
+
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) = tokio::join!(
+        server.clone().accept(server_io),
+        client.clone().connect("s1.testing-server.playit.cloud", client_io)
+    );
+    let mut server_io = server_io.unwrap();
+    let mut client_io = client_io.unwrap();
+
+    client_io.write_all(b"hello").await.unwrap();
+    client_io.flush().await.unwrap();
+    let mut buf = [0u8; 5];
+    server_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(server.accept(server_io), client.connect("other.playit.cloud", client_io));
+    assert!(server_io.is_err());
+    assert!(client_io.is_err());
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn tower_service_test() {
+    use tower_service::Service;
+
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .with_alpn(vec![b"h2".to_vec()])
+        .into_acceptor()
+        .unwrap(),
+    );
+    let mut connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .with_alpn(vec![b"h2".to_vec()])
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move {
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().await.unwrap();
+            acceptor.accept(stream).await.unwrap();
+        }
+    });
+
+    std::future::poll_fn(|cx| Service::<std::net::SocketAddr>::poll_ready(&mut connector, cx))
+        .await
+        .unwrap();
+    let stream = connector.call(addr).await.unwrap();
+    assert!(stream.is_encrypted());
+    connector.call(addr).await.unwrap();
+
+    server_task.await.unwrap();
+}
+
+#[cfg(all(feature = "hyper", feature = "test-util"))]
+#[tokio::test]
+async fn hyper_connector_test() {
+    use hyper_util::client::legacy::connect::Connection;
+    use tower_service::Service;
+
+    crate::install_crypto().unwrap();
+    let (server, client) = crate::testing::generate_self_signed(&["127.0.0.1"]);
+    let acceptor = ClientAcceptor::tls(
+        server.with_alpn(vec![b"h2".to_vec()]).into_acceptor().unwrap(),
+    );
+    // built for another name, the URI host decides what is verified
+    let mut connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        client.with_alpn(vec![b"h2".to_vec()]).into_connector().unwrap(),
+    )
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_task = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        assert!(acceptor.accept(stream).await.unwrap().is_encrypted());
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        buf
+    });
+
+    let uri = format!("https://{}/", addr).parse::<hyper::Uri>().unwrap();
+    let stream = connector.call(uri).await.unwrap();
+    assert!(stream.get_ref().is_encrypted());
+    assert!(stream.connected().is_negotiated_h2());
+
+    let uri = format!("http://{}/", addr).parse::<hyper::Uri>().unwrap();
+    let mut stream = connector.call(uri).await.unwrap().into_inner();
+    assert!(stream.is_plaintext());
+    stream.write_all(b"GET /").await.unwrap();
+    assert_eq!(&server_task.await.unwrap(), b"GET /");
+}
+
+#[cfg(feature = "hyper")]
+#[tokio::test]
+async fn hyper_server_stream_test() {
+    use hyper_util::rt::TokioIo;
+
+    use crate::service::HyperServerStream;
+
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client_io = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let client_addr = client_io.local_addr().unwrap();
+    let (server_io, _) = listener.accept().await.unwrap();
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+    let mut client_io = client_io.unwrap();
+
+    let server_io = HyperServerStream::from(server_io.unwrap());
+    assert_eq!(server_io.peer_addr().unwrap(), client_addr);
+    let info = server_io.tls_info().unwrap();
+    assert_eq!(info.server_name.as_deref(), Some("s1.testing-server.playit.cloud"));
+
+    let mut server_io = TokioIo::new(server_io);
+    client_io.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    client_io.flush().await.unwrap();
+    let mut buf = [0u8; 16];
+    server_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"GET / HTTP/1.1\r\n");
+
+    server_io.write_all(b"HTTP/1.1 200 OK\r\n").await.unwrap();
+    server_io.flush().await.unwrap();
+    let mut buf = [0u8; 17];
+    client_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"HTTP/1.1 200 OK\r\n");
+}
+
+#[test]
+fn system_roots_cache_test() {
+    crate::install_crypto().unwrap();
+    let first = crate::tls_setup::system_roots().unwrap();
+    let second = crate::tls_setup::system_roots().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(!first.is_empty());
+
+    TlsSetup::reload_system_roots().unwrap();
+    let reloaded = crate::tls_setup::system_roots().unwrap();
+    assert!(!Arc::ptr_eq(&first, &reloaded));
+    assert_eq!(first.len(), reloaded.len());
+
+    TlsSetup::client_system_roots().unwrap().into_connector().unwrap();
+}
+
+#[tokio::test]
+async fn with_added_ca_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let old = ClientConnector::from_client_tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/client_ca.crt")).unwrap(),
+    )
+    .unwrap();
+    let new = old.with_added_ca(include_bytes!("./res/server_ca.crt")).unwrap();
+
+    for (connector, should_connect) in [(&old, false), (&new, true)] {
+        let (server_io, client_io) = duplex(8192);
+        let (server_io, client_io) =
+            tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+        assert_eq!(server_io.is_ok(), should_connect);
+        assert_eq!(client_io.is_ok(), should_connect);
+    }
+
+    let error = new.with_added_ca(b"not a pem").err().unwrap();
+    assert!(matches!(error, TlsError::NoCertificates));
+
+    let raw = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/client_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+    let error = raw.with_added_ca(include_bytes!("./res/server_ca.crt")).err().unwrap();
+    let TlsError::Io(error) = error else {
+        panic!("expected io error, got {:?}", error);
+    };
+    assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[tokio::test]
+async fn require_alpn_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = |alpn: Vec<Vec<u8>>| {
+        ClientAcceptor::tls(
+            TlsSetup::build_server(
+                include_bytes!("./res/server.key"),
+                include_bytes!("./res/server.crt"),
+            )
+            .unwrap()
+            .with_alpn(alpn)
+            .into_acceptor()
+            .unwrap(),
+        )
+    };
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .with_alpn(vec![b"h2".to_vec(), b"http/1.1".to_vec()])
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap()
+    .require_alpn(&[b"h2"]);
+
+    let h2 = acceptor(vec![b"h2".to_vec()]);
+    let (server_io, client_io) = duplex(8192);
+    let (server_io, client_io) =
+        tokio::join!(h2.accept(server_io), connector.connect(client_io));
+    let _server_io = server_io.unwrap();
+    assert_eq!(client_io.unwrap().alpn_protocol(), Some(&b"h2"[..]));
+
+    for alpn in [vec![b"http/1.1".to_vec()], Vec::new()] {
+        let acceptor = acceptor(alpn);
+        let (server_io, client_io) = duplex(8192);
+        let (_server_io, client_io) =
+            tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+        let error = client_io.err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("h2"));
+    }
+
+    let (_server_io, client_io) = duplex(8192);
+    let plain = ClientConnector::plain().require_alpn(&[b"h2"]);
+    assert!(plain.connect(client_io).await.unwrap().is_plaintext());
+}
+
+#[tokio::test]
+async fn starttls_upgrade_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap()
+        .into_acceptor()
+        .unwrap(),
+    );
+    let connector = ClientConnector::tls(
+        "s1.testing-server.playit.cloud",
+        TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+            .unwrap()
+            .into_connector()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (server_io, client_io) = duplex(8192);
+    let server_task = tokio::spawn(async move {
+        let mut server_io = ClientAcceptor::plain().accept(server_io).await.unwrap();
+        let mut buf = [0u8; 10];
+        server_io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"STARTTLS\r\n");
+        server_io.write_all(b"220 ready\r\n").await.unwrap();
+
+        let mut server_io = acceptor.upgrade(server_io).await.unwrap();
+        assert!(server_io.is_encrypted());
+        let mut buf = [0u8; 4];
+        server_io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"EHLO");
+
+        let error = acceptor.upgrade(server_io).await.err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        let error = ClientAcceptor::plain()
+            .upgrade(ServerStream::TcpStream(duplex(64).0))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    });
+
+    let mut client_io = ClientConnector::plain().connect(client_io).await.unwrap();
+    client_io.write_all(b"STARTTLS\r\n").await.unwrap();
+    let mut buf = [0u8; 11];
+    client_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"220 ready\r\n");
+
+    let error = ClientConnector::plain()
+        .upgrade(ClientStream::TcpStream(duplex(64).0), "s1.testing-server.playit.cloud")
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    let mut client_io =
+        connector.upgrade(client_io, "s1.testing-server.playit.cloud").await.unwrap();
+    assert!(client_io.is_encrypted());
+    client_io.write_all(b"EHLO").await.unwrap();
+    client_io.flush().await.unwrap();
+    server_task.await.unwrap();
+
+    let error = connector
+        .upgrade(client_io, "s1.testing-server.playit.cloud")
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn key_mismatch_test() {
+    crate::install_crypto().unwrap();
+    let server_crt = include_bytes!("./res/server.crt");
+    let wrong_key = include_bytes!("./res/client.key");
+
+    let error = TlsSetup::build_server(wrong_key, server_crt).err().unwrap();
+    assert!(matches!(error, TlsError::KeyMismatch));
+
+    let error =
+        TlsSetup::build_mutual(include_bytes!("./res/client_ca.crt"), wrong_key, server_crt)
+            .err()
+            .unwrap();
+    let error = std::io::Error::from(error);
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "private key does not match certificate");
+}
+
+#[test]
+fn active_parameters_test() {
+    crate::install_crypto().unwrap();
+    let suites = TlsSetup::active_cipher_suites().unwrap();
+    assert!(!suites.is_empty());
+    assert_eq!(
+        TlsSetup::active_protocol_versions().unwrap(),
+        vec![&rustls::version::TLS13, &rustls::version::TLS12]
+    );
+
+    let server = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap();
+    assert_eq!(server.active_cipher_suites().unwrap().len(), suites.len());
+
+    let hardened = server.clone().hardened();
+    assert_eq!(hardened.active_protocol_versions().unwrap(), vec![&rustls::version::TLS13]);
+    assert!(hardened
+        .active_cipher_suites()
+        .unwrap()
+        .iter()
+        .all(|suite| suite.version() == &rustls::version::TLS13));
+
+    let tls13_suites = suites
+        .iter()
+        .copied()
+        .filter(|suite| suite.version() == &rustls::version::TLS13)
+        .collect();
+    let client = TlsSetup::build_client(include_bytes!("./res/server_ca.crt")).unwrap();
+    let client = ClientTls::from(client).with_cipher_suites(tls13_suites);
+    assert_eq!(client.active_protocol_versions().unwrap(), vec![&rustls::version::TLS13]);
+}
+
+async fn read_proxy_request(stream: &mut tokio::net::TcpStream) -> String {
+    let mut request = Vec::new();
+    while !request.ends_with(b"\r\n\r\n") {
+        request.push(stream.read_u8().await.unwrap());
+    }
+    String::from_utf8(request).unwrap()
+}
+
+#[tokio::test]
+async fn proxy_connection_builder_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+    let connector = ClientConnector::tls("s1.testing-server.playit.cloud", connector).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = ProxyConnectionBuilder::new(listener.local_addr().unwrap(), connector)
+        .with_authorization("Basic dXNlcjpwYXNz")
+        .unwrap();
+    let error = proxy
+        .clone()
+        .with_authorization("Basic dXNlcjpwYXNz\r\nX-Injected: 1")
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    let proxy_task = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let request = read_proxy_request(&mut stream).await;
+        assert!(request.starts_with("CONNECT s1.testing-server.playit.cloud:443 HTTP/1.1\r\n"));
+        assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+        stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+        let mut stream = acceptor.accept(stream).await.unwrap();
+        stream.write_all(b"tunneled").await.unwrap();
+        stream.flush().await.unwrap();
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        read_proxy_request(&mut stream).await;
+        stream
+            .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+            .await
+            .unwrap();
+    });
+
+    let mut client_io = proxy.connect_host("s1.testing-server.playit.cloud:443").await.unwrap();
+    assert!(client_io.is_encrypted());
+    let mut buf = [0u8; 8];
+    client_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"tunneled");
+
+    let target = "127.0.0.1:443".parse().unwrap();
+    let error = ConnectionBuilder::connect(&proxy, target).await.err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(error.to_string().contains("407"));
+    proxy_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn socks5_connection_builder_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = TlsSetup::build_server(
+        include_bytes!("./res/server.key"),
+        include_bytes!("./res/server.crt"),
+    )
+    .unwrap()
+    .into_acceptor()
+    .unwrap();
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+    let connector = ClientConnector::tls("s1.testing-server.playit.cloud", connector).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = Socks5ConnectionBuilder::new(listener.local_addr().unwrap(), connector)
+        .with_credentials("user", "pass");
+
+    let proxy_task = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 4];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [5, 2, 0, 2]);
+        stream.write_all(&[5, 2]).await.unwrap();
+        let mut auth = [0u8; 11];
+        stream.read_exact(&mut auth).await.unwrap();
+        assert_eq!(&auth, b"\x01\x04user\x04pass");
+        stream.write_all(&[1, 0]).await.unwrap();
+
+        let host = b"s1.testing-server.playit.cloud";
+        let mut request = vec![0u8; 5 + host.len() + 2];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request[..5], &[5, 1, 0, 3, host.len() as u8]);
+        assert_eq!(&request[5..5 + host.len()], host);
+        assert_eq!(&request[5 + host.len()..], &443u16.to_be_bytes());
+        stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80]).await.unwrap();
+
+        let mut stream = acceptor.accept(stream).await.unwrap();
+        stream.write_all(b"tunneled").await.unwrap();
+        stream.flush().await.unwrap();
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        stream.read_exact(&mut greeting).await.unwrap();
+        stream.write_all(&[5, 0]).await.unwrap();
+        let mut request = [0u8; 22];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request[..4], &[5, 1, 0, 4]);
+        assert_eq!(&request[4..20], &std::net::Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(&request[20..], &8443u16.to_be_bytes());
+        stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).await.unwrap();
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        stream.read_exact(&mut greeting).await.unwrap();
+        stream.write_all(&[5, 2]).await.unwrap();
+        stream.read_exact(&mut auth).await.unwrap();
+        stream.write_all(&[5, 0]).await.unwrap();
+    });
+
+    let mut client_io = proxy.connect_host("s1.testing-server.playit.cloud:443").await.unwrap();
+    assert!(client_io.is_encrypted());
+    let mut buf = [0u8; 8];
+    client_io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"tunneled");
+
+    let target = "[::1]:8443".parse().unwrap();
+    let error = ConnectionBuilder::connect(&proxy, target).await.err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused);
+
+    let error = proxy.connect_host("s1.testing-server.playit.cloud:443").await.err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    proxy_task.await.unwrap();
+}
+
+#[test]
+fn fips_test() {
+    crate::install_crypto().unwrap();
+    assert_eq!(crate::is_fips(), cfg!(feature = "fips"));
+
+    let suites = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .active_cipher_suites()
+        .unwrap();
+    assert!(!suites.is_empty());
+    if cfg!(feature = "fips") {
+        assert!(suites.iter().all(|suite| suite.fips()));
+    }
+}
+
+#[test]
+fn install_crypto_conflict_test() {
+    crate::install_crypto().unwrap();
+    crate::install_crypto().unwrap();
+    let name = crate::installed_provider_name().unwrap();
+    assert!(["aws-lc-rs", "aws-lc-rs-fips", "ring"].contains(&name));
+
+    crate::install_crypto_named(name, default_provider()).unwrap();
+
+    let error = crate::install_crypto_named("other", default_provider()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(
+        error.to_string(),
+        format!("crypto provider {} is already installed, cannot install other", name)
+    );
+
+    let error = crate::install_crypto_with(default_provider()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(crate::installed_provider_name(), Some(name));
+}
+
+#[tokio::test]
+async fn peer_addr_test() {
+    crate::install_crypto().unwrap();
+    let acceptor = ClientAcceptor::from_server_tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+    let connector = ClientConnector::tls("s1.testing-server.playit.cloud", connector).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    let accept_task = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let tls = acceptor.accept(stream).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        (tls, ClientAcceptor::plain().accept(stream).await.unwrap())
+    });
+
+    let client_tls = ConnectionBuilder::connect(&connector, server_addr).await.unwrap();
+    let client_plain = ConnectionBuilder::connect(&ClientConnector::plain(), server_addr)
+        .await
+        .unwrap();
+    let (server_tls, server_plain) = accept_task.await.unwrap();
+
+    assert!(client_tls.is_encrypted());
+    assert_eq!(client_tls.peer_addr().unwrap(), server_addr);
+    assert_eq!(client_plain.peer_addr().unwrap(), server_addr);
+    assert_eq!(server_tls.peer_addr().unwrap(), client_tls.get_ref().local_addr().unwrap());
+    assert_eq!(server_plain.peer_addr().unwrap(), client_plain.get_ref().local_addr().unwrap());
+}
+
+async fn tls_pair(
+    write_buffer_limit: usize,
+) -> (ServerStream<DuplexStream>, ClientStream<DuplexStream>) {
+    let acceptor = ClientAcceptor::from_server_tls(
+        TlsSetup::build_server(
+            include_bytes!("./res/server.key"),
+            include_bytes!("./res/server.crt"),
+        )
+        .unwrap(),
+    )
+    .unwrap()
+    .with_write_buffer_limit(write_buffer_limit);
+    let connector = TlsSetup::build_client(include_bytes!("./res/server_ca.crt"))
+        .unwrap()
+        .into_connector()
+        .unwrap();
+    let connector = ClientConnector::tls("s1.testing-server.playit.cloud", connector)
+        .unwrap()
+        .with_write_buffer_limit(write_buffer_limit);
+
+    let (server_io, client_io) = duplex(16 * 1024);
+    let (server_io, client_io) =
+        tokio::join!(acceptor.accept(server_io), connector.connect(client_io));
+    (server_io.unwrap(), client_io.unwrap())
+}
+
+async fn bulk_transfer(write_buffer_limit: usize, total: usize) {
+    let (mut server_io, mut client_io) = tls_pair(write_buffer_limit).await;
+    let reader = tokio::spawn(async move {
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut received = 0;
+        loop {
+            match server_io.read(&mut buf).await.unwrap() {
+                0 => break received,
+                n => received += n,
+            }
+        }
+    });
+
+    let chunk = vec![7u8; 256 * 1024];
+    let mut sent = 0;
+    while sent < total {
+        let len = chunk.len().min(total - sent);
+        client_io.write_all(&chunk[..len]).await.unwrap();
+        sent += len;
+    }
+    client_io.shutdown().await.unwrap();
+
+    assert_eq!(reader.await.unwrap(), total);
+}
+
+#[tokio::test]
+async fn write_buffer_limit_test() {
+    crate::install_crypto().unwrap();
+    bulk_transfer(1024, 1024 * 1024).await;
+    bulk_transfer(1024 * 1024, 1024 * 1024).await;
+}
+
+#[tokio::test]
+async fn write_buffer_limit_stall_test() {
+    crate::install_crypto().unwrap();
+
+    // bytes a write accepts while the peer reads nothing
+    async fn buffered(write_buffer_limit: usize) -> usize {
+        let (mut server_io, mut client_io) = tls_pair(write_buffer_limit).await;
+        let chunk = vec![7u8; 16 * 1024];
+        let mut buffered = 0;
+        while let Ok(n) =
+            tokio::time::timeout(Duration::from_millis(50), client_io.write(&chunk)).await
+        {
+            buffered += n.unwrap();
+            assert!(buffered < 4 * 1024 * 1024, "write never stalled");
+        }
+
+        // draining the peer lets the stalled write continue
+        let mut buf = vec![0u8; 64 * 1024];
+        let (read, written) = tokio::join!(server_io.read(&mut buf), client_io.write(&chunk));
+        assert!(read.unwrap() > 0);
+        assert!(written.unwrap() > 0);
+        buffered
+    }
+
+    let small = buffered(4 * 1024).await;
+    let large = buffered(1024 * 1024).await;
+    assert!(small < 64 * 1024, "{}", small);
+    assert!(large > 512 * 1024, "{}", large);
+}
+
+#[test]
+fn build_from_readers_test() {
+    crate::install_crypto().unwrap();
+    let chain = std::str::from_utf8(include_bytes!("./res/server.crt")).unwrap();
+    let key = std::str::from_utf8(include_bytes!("./res/server.key")).unwrap();
+    let bundle = format!(
+        "\u{feff}subject=CN = s1.testing-server.playit.cloud\nissuer=CN = intermediate\n{}",
+        chain
+    )
+    .replace('\n', "\r\n");
+
+    let server = TlsSetup::build_mutual_from_readers(
+        std::io::BufReader::with_capacity(7, &include_bytes!("./res/client_ca.crt")[..]),
+        key.as_bytes(),
+        std::io::BufReader::with_capacity(7, bundle.as_bytes()),
+    )
+    .unwrap();
+    let expected = TlsSetup::build_server(key.as_bytes(), chain.as_bytes()).unwrap();
+    assert_eq!(server.subject_alt_names(), expected.subject_alt_names());
+    server.into_acceptor().unwrap();
+
+    for capacity in [1, 2, 7, 64] {
+        let certs = crate::tls_setup::read_certificates(std::io::BufReader::with_capacity(
+            capacity,
+            bundle.as_bytes(),
+        ))
+        .unwrap();
+        assert_eq!(certs, crate::tls_setup::parse_certificates(chain.as_bytes()).unwrap());
+    }
+
+    // starts with 0x30 like DER, but is PEM
+    let text_header = format!("0 intermediates\r\n{}", chain.replace('\n', "\r\n"));
+    let certs = crate::tls_setup::read_certificates(text_header.as_bytes()).unwrap();
+    assert_eq!(certs.len(), 3);
+
+    let combined = format!("{}{}", key, chain);
+    TlsSetup::build_server_from_readers(combined.as_bytes(), std::io::empty()).unwrap();
+
+    let error = TlsSetup::build_server_from_readers(
+        &include_bytes!("./res/client.key")[..],
+        chain.as_bytes(),
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(error, TlsError::KeyMismatch));
+}
@@ -0,0 +1,73 @@
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+    KeyUsagePurpose,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::RootCertStore;
+
+use crate::tls_setup::MutualTls;
+
+/// CN of the client certificate made by [`generate_self_signed`].
+pub const CLIENT_NAME: &str = "tls-friend test client";
+
+/// Generate a throwaway CA with a server certificate for `names` and a client certificate,
+/// both signed by it. The first name is also used as the server certificate's CN.
+///
+/// Returns `(server, client)`, each trusting the CA. Panics if `names` is empty or contains
+/// an invalid DNS name or IP address.
+pub fn generate_self_signed(names: &[&str]) -> (MutualTls, MutualTls) {
+    assert!(!names.is_empty(), "at least one server name is required");
+
+    let mut ca_params = CertificateParams::new(Vec::new()).expect("empty SAN list");
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name.push(DnType::CommonName, "tls-friend test CA");
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+
+    let ca_key = KeyPair::generate().expect("generate CA key");
+    let ca_cert = ca_params.self_signed(&ca_key).expect("self sign CA");
+    let issuer = Issuer::new(ca_params, ca_key);
+
+    let mut trust = RootCertStore::empty();
+    trust.add(ca_cert.der().clone()).expect("add generated CA");
+
+    let names = names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let (server_chain, server_key) = leaf(
+        &issuer,
+        CertificateParams::new(names.clone()).expect("valid server names"),
+        &names[0],
+        ExtendedKeyUsagePurpose::ServerAuth,
+    );
+    let (client_chain, client_key) = leaf(
+        &issuer,
+        CertificateParams::new(Vec::new()).expect("empty SAN list"),
+        CLIENT_NAME,
+        ExtendedKeyUsagePurpose::ClientAuth,
+    );
+
+    let server = MutualTls::from_parts(trust.clone(), server_chain, server_key)
+        .expect("generated server identity");
+    let client = MutualTls::from_parts(trust, client_chain, client_key)
+        .expect("generated client identity");
+
+    (server, client)
+}
+
+fn leaf(
+    issuer: &Issuer<'_, KeyPair>,
+    mut params: CertificateParams,
+    common_name: &str,
+    usage: ExtendedKeyUsagePurpose,
+) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+    params.distinguished_name.push(DnType::CommonName, common_name);
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![usage];
+    params.use_authority_key_identifier_extension = true;
+
+    let key = KeyPair::generate().expect("generate leaf key");
+    let cert = params.signed_by(&key, issuer).expect("sign leaf");
+
+    (
+        vec![cert.der().clone()],
+        PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+    )
+}
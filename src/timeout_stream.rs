@@ -0,0 +1,113 @@
+use std::future::Future;
+use std::io::{Error, IoSlice};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// Fails reads and writes with `TimedOut` if the inner IO makes no progress for the
+/// configured duration. The deadline restarts every time a poll completes.
+pub struct TimeoutStream<IO> {
+    io: IO,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    read_deadline: Option<Pin<Box<Sleep>>>,
+    write_deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl<IO> TimeoutStream<IO> {
+    pub fn new(io: IO, read_timeout: Duration, write_timeout: Duration) -> Self {
+        TimeoutStream {
+            io,
+            read_timeout,
+            write_timeout,
+            read_deadline: None,
+            write_deadline: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &IO {
+        &self.io
+    }
+
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+
+    pub fn into_inner(self) -> IO {
+        self.io
+    }
+}
+
+fn poll_deadline<T>(
+    result: Poll<std::io::Result<T>>,
+    deadline: &mut Option<Pin<Box<Sleep>>>,
+    timeout: Duration,
+    cx: &mut Context<'_>,
+) -> Poll<std::io::Result<T>> {
+    if result.is_ready() {
+        *deadline = None;
+        return result;
+    }
+
+    let sleep = deadline.get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+    match sleep.as_mut().poll(cx) {
+        Poll::Ready(()) => {
+            *deadline = None;
+            Poll::Ready(Err(Error::new(std::io::ErrorKind::TimedOut, "io timed out")))
+        }
+        Poll::Pending => Poll::Pending,
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for TimeoutStream<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.io).poll_read(cx, buf);
+        poll_deadline(result, &mut this.read_deadline, this.read_timeout, cx)
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for TimeoutStream<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.io).poll_write(cx, buf);
+        poll_deadline(result, &mut this.write_deadline, this.write_timeout, cx)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.io).poll_flush(cx);
+        poll_deadline(result, &mut this.write_deadline, this.write_timeout, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.io).poll_shutdown(cx);
+        poll_deadline(result, &mut this.write_deadline, this.write_timeout, cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, Error>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.io).poll_write_vectored(cx, bufs);
+        poll_deadline(result, &mut this.write_deadline, this.write_timeout, cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+}
@@ -1,12 +1,45 @@
-use std::io::Error;
+use std::io::{Error, IoSlice};
+use std::net::SocketAddr;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, ReadHalf, WriteHalf,
+};
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::{CommonState, ProtocolVersion, SupportedCipherSuite};
 
-use crate::async_io::AsyncIO;
+use crate::async_io::{AsyncIO, HasPeerAddr};
+use crate::buf_stream::BufTlsStream;
+use crate::counting_stream::{ByteCounters, CountingStream};
+use crate::timeout_stream::TimeoutStream;
 use crate::{ClientTlsStream, ServerTlsStream};
 
+/// Owned snapshot of a negotiated TLS session, see `tls_info` on the stream types.
+#[derive(Clone, Debug)]
+pub struct TlsInfo {
+    pub version: ProtocolVersion,
+    pub cipher_suite: SupportedCipherSuite,
+    pub alpn: Option<Vec<u8>>,
+    /// SNI sent by the client, only known on the server side.
+    pub server_name: Option<String>,
+    pub peer_certificates: Vec<CertificateDer<'static>>,
+}
+
+impl TlsInfo {
+    fn from_state(state: &CommonState, server_name: Option<&str>) -> Option<Self> {
+        Some(TlsInfo {
+            version: state.protocol_version()?,
+            cipher_suite: state.negotiated_cipher_suite()?,
+            alpn: state.alpn_protocol().map(<[u8]>::to_vec),
+            server_name: server_name.map(str::to_string),
+            peer_certificates: state.peer_certificates().map(<[_]>::to_vec).unwrap_or_default(),
+        })
+    }
+}
+
 #[allow(clippy::large_enum_variant)]
 pub enum MaybeTlsStream<IO: AsyncIO> {
     Client(ClientStream<IO>),
@@ -25,17 +58,409 @@ pub enum ServerStream<IO: AsyncIO> {
     TlsStream(ServerTlsStream<IO>),
 }
 
+impl<IO: AsyncIO> MaybeTlsStream<IO> {
+    /// Split into independent read and write halves. For TLS both halves share the one
+    /// rustls session behind a lock, rejoin them with [`AsyncIO::try_join`].
+    pub fn into_split(self) -> (ReadHalf<Self>, WriteHalf<Self>) {
+        tokio::io::split(self)
+    }
+
+    pub fn get_ref(&self) -> &IO {
+        match self {
+            Self::Client(io) => io.get_ref(),
+            Self::Server(io) => io.get_ref(),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut IO {
+        match self {
+            Self::Client(io) => io.get_mut(),
+            Self::Server(io) => io.get_mut(),
+        }
+    }
+
+    /// Unwrap the underlying IO. For TLS the session is dropped without sending
+    /// close_notify and any buffered plaintext is lost.
+    pub fn into_inner(self) -> IO {
+        match self {
+            Self::Client(io) => io.into_inner(),
+            Self::Server(io) => io.into_inner(),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        match self {
+            Self::Client(io) => io.is_encrypted(),
+            Self::Server(io) => io.is_encrypted(),
+        }
+    }
+
+    pub fn is_plaintext(&self) -> bool {
+        !self.is_encrypted()
+    }
+
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match self {
+            Self::Client(io) => io.alpn_protocol(),
+            Self::Server(io) => io.alpn_protocol(),
+        }
+    }
+
+    pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        match self {
+            Self::Client(io) => io.peer_certificates(),
+            Self::Server(io) => io.peer_certificates(),
+        }
+    }
+
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        match self {
+            Self::Client(io) => io.protocol_version(),
+            Self::Server(io) => io.protocol_version(),
+        }
+    }
+
+    pub fn negotiated_cipher_suite(&self) -> Option<SupportedCipherSuite> {
+        match self {
+            Self::Client(io) => io.negotiated_cipher_suite(),
+            Self::Server(io) => io.negotiated_cipher_suite(),
+        }
+    }
+
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        match self {
+            Self::Client(io) => io.tls_info(),
+            Self::Server(io) => io.tls_info(),
+        }
+    }
+
+    pub fn received_close_notify(&mut self) -> bool {
+        match self {
+            Self::Client(io) => io.received_close_notify(),
+            Self::Server(io) => io.received_close_notify(),
+        }
+    }
+
+    pub fn with_timeouts(self, read: Duration, write: Duration) -> TimeoutStream<Self> {
+        TimeoutStream::new(self, read, write)
+    }
+
+    /// Wrap in a [`CountingStream`], also returning its counters to read from another task.
+    pub fn counted(self) -> (CountingStream<Self>, Arc<ByteCounters>) {
+        let stream = CountingStream::new(self);
+        let counters = stream.counters();
+        (stream, counters)
+    }
+
+    /// Wrap in a [`BufTlsStream`] for `read_line` and other buffered reads.
+    pub fn buffered(self) -> BufTlsStream<Self> {
+        BufTlsStream::new(self)
+    }
+
+    /// Flush, send close_notify and for TLS wait for the peer's close_notify. Anything the
+    /// peer sends before its close_notify is discarded. Returns `UnexpectedEof` if the peer
+    /// closed the connection without a close_notify, which may indicate truncation.
+    pub async fn close(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Client(io) => io.close().await,
+            Self::Server(io) => io.close().await,
+        }
+    }
+
+    /// Shut down the write side (sending close_notify for TLS). Returns `Ok(false)` if the
+    /// deadline passed before the shutdown could be flushed.
+    pub async fn shutdown_with_timeout(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        match tokio::time::timeout(timeout, self.shutdown()).await {
+            Ok(result) => result.map(|_| true),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+impl<IO: AsyncIO> ClientStream<IO> {
+    /// Split into independent read and write halves. For TLS both halves share the one
+    /// rustls session behind a lock, rejoin them with [`AsyncIO::try_join`].
+    pub fn into_split(self) -> (ReadHalf<Self>, WriteHalf<Self>) {
+        tokio::io::split(self)
+    }
+
+    pub fn get_ref(&self) -> &IO {
+        match self {
+            Self::TcpStream(io) => io,
+            Self::TlsStream(io) => io.get_ref().0,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut IO {
+        match self {
+            Self::TcpStream(io) => io,
+            Self::TlsStream(io) => io.get_mut().0,
+        }
+    }
+
+    /// Unwrap the underlying IO. For TLS the session is dropped without sending
+    /// close_notify and any buffered plaintext is lost.
+    pub fn into_inner(self) -> IO {
+        match self {
+            Self::TcpStream(io) => io,
+            Self::TlsStream(io) => io.into_inner().0,
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Self::TlsStream(_))
+    }
+
+    pub fn is_plaintext(&self) -> bool {
+        !self.is_encrypted()
+    }
+
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.alpn_protocol(),
+        }
+    }
+
+    pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.peer_certificates(),
+        }
+    }
+
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.protocol_version(),
+        }
+    }
+
+    pub fn negotiated_cipher_suite(&self) -> Option<SupportedCipherSuite> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.negotiated_cipher_suite(),
+        }
+    }
+
+    /// Whether the peer sent a TLS close_notify. Check after reading to EOF to tell a clean
+    /// close from a truncated connection, which is always `false` for plaintext. Takes
+    /// `&mut self` as rustls reports it only after processing the buffered records.
+    pub fn received_close_notify(&mut self) -> bool {
+        match self {
+            Self::TcpStream(_) => false,
+            Self::TlsStream(io) => io
+                .get_mut()
+                .1
+                .process_new_packets()
+                .is_ok_and(|state| state.peer_has_closed()),
+        }
+    }
+
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => TlsInfo::from_state(io.get_ref().1, None),
+        }
+    }
+
+    pub fn with_timeouts(self, read: Duration, write: Duration) -> TimeoutStream<Self> {
+        TimeoutStream::new(self, read, write)
+    }
+
+    /// Wrap in a [`CountingStream`], also returning its counters to read from another task.
+    pub fn counted(self) -> (CountingStream<Self>, Arc<ByteCounters>) {
+        let stream = CountingStream::new(self);
+        let counters = stream.counters();
+        (stream, counters)
+    }
+
+    /// Wrap in a [`BufTlsStream`] for `read_line` and other buffered reads.
+    pub fn buffered(self) -> BufTlsStream<Self> {
+        BufTlsStream::new(self)
+    }
+
+    /// Flush, send close_notify and for TLS wait for the peer's close_notify. Anything the
+    /// peer sends before its close_notify is discarded. Returns `UnexpectedEof` if the peer
+    /// closed the connection without a close_notify, which may indicate truncation.
+    pub async fn close(&mut self) -> std::io::Result<()> {
+        self.shutdown().await?;
+
+        if let Self::TlsStream(_) = self {
+            let mut buf = [0u8; 1024];
+            while self.read(&mut buf).await? != 0 {}
+        }
+
+        Ok(())
+    }
+
+    /// Shut down the write side (sending close_notify for TLS). Returns `Ok(false)` if the
+    /// deadline passed before the shutdown could be flushed.
+    pub async fn shutdown_with_timeout(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        match tokio::time::timeout(timeout, self.shutdown()).await {
+            Ok(result) => result.map(|_| true),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+impl<IO: AsyncIO> ServerStream<IO> {
+    /// Split into independent read and write halves. For TLS both halves share the one
+    /// rustls session behind a lock, rejoin them with [`AsyncIO::try_join`].
+    pub fn into_split(self) -> (ReadHalf<Self>, WriteHalf<Self>) {
+        tokio::io::split(self)
+    }
+
+    pub fn get_ref(&self) -> &IO {
+        match self {
+            Self::TcpStream(io) => io,
+            Self::TlsStream(io) => io.get_ref().0,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut IO {
+        match self {
+            Self::TcpStream(io) => io,
+            Self::TlsStream(io) => io.get_mut().0,
+        }
+    }
+
+    /// Unwrap the underlying IO. For TLS the session is dropped without sending
+    /// close_notify and any buffered plaintext is lost.
+    pub fn into_inner(self) -> IO {
+        match self {
+            Self::TcpStream(io) => io,
+            Self::TlsStream(io) => io.into_inner().0,
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Self::TlsStream(_))
+    }
+
+    pub fn is_plaintext(&self) -> bool {
+        !self.is_encrypted()
+    }
+
+    /// The negotiated ALPN protocol. rustls does not keep the client's full offer after the
+    /// handshake, a [`HandshakeObserver`] on the acceptor receives it instead.
+    ///
+    /// [`HandshakeObserver`]: crate::observer::HandshakeObserver
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.alpn_protocol(),
+        }
+    }
+
+    pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.peer_certificates(),
+        }
+    }
+
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.protocol_version(),
+        }
+    }
+
+    pub fn negotiated_cipher_suite(&self) -> Option<SupportedCipherSuite> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.negotiated_cipher_suite(),
+        }
+    }
+
+    pub fn server_name(&self) -> Option<&str> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => io.get_ref().1.server_name(),
+        }
+    }
+
+    /// Whether the peer sent a TLS close_notify. Check after reading to EOF to tell a clean
+    /// close from a truncated connection, which is always `false` for plaintext. Takes
+    /// `&mut self` as rustls reports it only after processing the buffered records.
+    pub fn received_close_notify(&mut self) -> bool {
+        match self {
+            Self::TcpStream(_) => false,
+            Self::TlsStream(io) => io
+                .get_mut()
+                .1
+                .process_new_packets()
+                .is_ok_and(|state| state.peer_has_closed()),
+        }
+    }
+
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        match self {
+            Self::TcpStream(_) => None,
+            Self::TlsStream(io) => {
+                let state = io.get_ref().1;
+                TlsInfo::from_state(state, state.server_name())
+            }
+        }
+    }
+
+    pub fn with_timeouts(self, read: Duration, write: Duration) -> TimeoutStream<Self> {
+        TimeoutStream::new(self, read, write)
+    }
+
+    /// Wrap in a [`CountingStream`], also returning its counters to read from another task.
+    pub fn counted(self) -> (CountingStream<Self>, Arc<ByteCounters>) {
+        let stream = CountingStream::new(self);
+        let counters = stream.counters();
+        (stream, counters)
+    }
+
+    /// Wrap in a [`BufTlsStream`] for `read_line` and other buffered reads.
+    pub fn buffered(self) -> BufTlsStream<Self> {
+        BufTlsStream::new(self)
+    }
+
+    /// Flush, send close_notify and for TLS wait for the peer's close_notify. Anything the
+    /// peer sends before its close_notify is discarded. Returns `UnexpectedEof` if the peer
+    /// closed the connection without a close_notify, which may indicate truncation.
+    pub async fn close(&mut self) -> std::io::Result<()> {
+        self.shutdown().await?;
+
+        if let Self::TlsStream(_) = self {
+            let mut buf = [0u8; 1024];
+            while self.read(&mut buf).await? != 0 {}
+        }
+
+        Ok(())
+    }
+
+    /// Shut down the write side (sending close_notify for TLS). Returns `Ok(false)` if the
+    /// deadline passed before the shutdown could be flushed.
+    pub async fn shutdown_with_timeout(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        match tokio::time::timeout(timeout, self.shutdown()).await {
+            Ok(result) => result.map(|_| true),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+impl<IO: AsyncIO + HasPeerAddr> MaybeTlsStream<IO> {
+    /// Address of the peer, read from the underlying socket.
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
 impl<IO: AsyncIO> AsyncRead for MaybeTlsStream<IO> {
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<std::io::Result<()>> {
-        unsafe {
-            match self.get_mut() {
-                Self::Client(io) => Pin::new_unchecked(io).poll_read(cx, buf),
-                Self::Server(io) => Pin::new_unchecked(io).poll_read(cx, buf),
-            }
+        match self.get_mut() {
+            Self::Client(io) => Pin::new(io).poll_read(cx, buf),
+            Self::Server(io) => Pin::new(io).poll_read(cx, buf),
         }
     }
 }
@@ -46,33 +471,51 @@ impl<IO: AsyncIO> AsyncWrite for MaybeTlsStream<IO> {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::Client(io) => Pin::new_unchecked(io).poll_write(cx, buf),
-                Self::Server(io) => Pin::new_unchecked(io).poll_write(cx, buf),
-            }
+        match self.get_mut() {
+            Self::Client(io) => Pin::new(io).poll_write(cx, buf),
+            Self::Server(io) => Pin::new(io).poll_write(cx, buf),
         }
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::Client(io) => Pin::new_unchecked(io).poll_flush(cx),
-                Self::Server(io) => Pin::new_unchecked(io).poll_flush(cx),
-            }
+        match self.get_mut() {
+            Self::Client(io) => Pin::new(io).poll_flush(cx),
+            Self::Server(io) => Pin::new(io).poll_flush(cx),
         }
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::Client(io) => Pin::new_unchecked(io).poll_shutdown(cx),
-                Self::Server(io) => Pin::new_unchecked(io).poll_shutdown(cx),
-            }
+        match self.get_mut() {
+            Self::Client(io) => Pin::new(io).poll_shutdown(cx),
+            Self::Server(io) => Pin::new(io).poll_shutdown(cx),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, Error>> {
+        match self.get_mut() {
+            Self::Client(io) => Pin::new(io).poll_write_vectored(cx, bufs),
+            Self::Server(io) => Pin::new(io).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Client(io) => io.is_write_vectored(),
+            Self::Server(io) => io.is_write_vectored(),
         }
     }
 }
 
+impl<IO: AsyncIO + HasPeerAddr> ClientStream<IO> {
+    /// Address of the peer, read from the underlying socket.
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
 
 impl<IO: AsyncIO> AsyncRead for ClientStream<IO> {
     fn poll_read(
@@ -80,11 +523,9 @@ impl<IO: AsyncIO> AsyncRead for ClientStream<IO> {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<std::io::Result<()>> {
-        unsafe {
-            match self.get_mut() {
-                Self::TcpStream(io) => Pin::new_unchecked(io).poll_read(cx, buf),
-                Self::TlsStream(io) => Pin::new_unchecked(io).poll_read(cx, buf),
-            }
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_read(cx, buf),
+            Self::TlsStream(io) => Pin::new(io).poll_read(cx, buf),
         }
     }
 }
@@ -95,31 +536,50 @@ impl<IO: AsyncIO> AsyncWrite for ClientStream<IO> {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::TcpStream(io) => Pin::new_unchecked(io).poll_write(cx, buf),
-                Self::TlsStream(io) => Pin::new_unchecked(io).poll_write(cx, buf),
-            }
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_write(cx, buf),
+            Self::TlsStream(io) => Pin::new(io).poll_write(cx, buf),
         }
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::TcpStream(io) => Pin::new_unchecked(io).poll_flush(cx),
-                Self::TlsStream(io) => Pin::new_unchecked(io).poll_flush(cx),
-            }
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_flush(cx),
+            Self::TlsStream(io) => Pin::new(io).poll_flush(cx),
         }
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::TcpStream(io) => Pin::new_unchecked(io).poll_shutdown(cx),
-                Self::TlsStream(io) => Pin::new_unchecked(io).poll_shutdown(cx),
-            }
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_shutdown(cx),
+            Self::TlsStream(io) => Pin::new(io).poll_shutdown(cx),
         }
     }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, Error>> {
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_write_vectored(cx, bufs),
+            Self::TlsStream(io) => Pin::new(io).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::TcpStream(io) => io.is_write_vectored(),
+            Self::TlsStream(io) => io.is_write_vectored(),
+        }
+    }
+}
+
+impl<IO: AsyncIO + HasPeerAddr> ServerStream<IO> {
+    /// Address of the peer, read from the underlying socket.
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
 }
 
 impl<IO: AsyncIO> AsyncRead for ServerStream<IO> {
@@ -128,11 +588,9 @@ impl<IO: AsyncIO> AsyncRead for ServerStream<IO> {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<std::io::Result<()>> {
-        unsafe {
-            match self.get_mut() {
-                Self::TcpStream(io) => Pin::new_unchecked(io).poll_read(cx, buf),
-                Self::TlsStream(io) => Pin::new_unchecked(io).poll_read(cx, buf),
-            }
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_read(cx, buf),
+            Self::TlsStream(io) => Pin::new(io).poll_read(cx, buf),
         }
     }
 }
@@ -143,29 +601,41 @@ impl<IO: AsyncIO> AsyncWrite for ServerStream<IO> {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::TcpStream(io) => Pin::new_unchecked(io).poll_write(cx, buf),
-                Self::TlsStream(io) => Pin::new_unchecked(io).poll_write(cx, buf),
-            }
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_write(cx, buf),
+            Self::TlsStream(io) => Pin::new(io).poll_write(cx, buf),
         }
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::TcpStream(io) => Pin::new_unchecked(io).poll_flush(cx),
-                Self::TlsStream(io) => Pin::new_unchecked(io).poll_flush(cx),
-            }
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_flush(cx),
+            Self::TlsStream(io) => Pin::new(io).poll_flush(cx),
         }
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
-        unsafe {
-            match self.get_mut() {
-                Self::TcpStream(io) => Pin::new_unchecked(io).poll_shutdown(cx),
-                Self::TlsStream(io) => Pin::new_unchecked(io).poll_shutdown(cx),
-            }
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_shutdown(cx),
+            Self::TlsStream(io) => Pin::new(io).poll_shutdown(cx),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, Error>> {
+        match self.get_mut() {
+            Self::TcpStream(io) => Pin::new(io).poll_write_vectored(cx, bufs),
+            Self::TlsStream(io) => Pin::new(io).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::TcpStream(io) => io.is_write_vectored(),
+            Self::TlsStream(io) => io.is_write_vectored(),
         }
     }
 }